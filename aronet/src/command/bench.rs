@@ -0,0 +1,126 @@
+use std::fmt::Display;
+use std::io;
+
+use clap::Args;
+use tokio::time::{Duration, Instant};
+
+use crate::utils::configuration::Config;
+use crate::utils::netlink::{Netlink, NetlinkError};
+use crate::utils::vici::{SocketConnector, ViciApi, ViciConnector};
+
+#[derive(Args, Debug)]
+pub struct BenchArgs {
+    /// round trips of each operation
+    #[arg(short = 'n', long, default_value_t = 10)]
+    count: u32,
+    /// interface looked up through netlink, exists without the daemon by default
+    #[arg(long, default_value = "lo")]
+    interface: String,
+}
+
+/// round-trip latencies of an operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Latency {
+    min: Duration,
+    avg: Duration,
+    max: Duration,
+}
+
+impl Display for Latency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "min {:?}, avg {:?}, max {:?}",
+            self.min, self.avg, self.max
+        )
+    }
+}
+
+/// Run `op` `count` times in sequence, stops at the first failure.
+async fn measure<T, E>(
+    count: u32,
+    mut op: impl AsyncFnMut() -> Result<T, E>,
+) -> Result<Latency, E> {
+    let count = count.max(1);
+    let mut samples = vec![];
+    for _ in 0..count {
+        let start = Instant::now();
+        op().await?;
+        samples.push(start.elapsed());
+    }
+
+    Ok(Latency {
+        min: samples.iter().copied().min().unwrap(),
+        avg: samples.iter().sum::<Duration>() / count,
+        max: samples.iter().copied().max().unwrap(),
+    })
+}
+
+/// `get_link` in the root netns, answered by the kernel
+async fn bench_netlink(nl: &Netlink, interface: &str, count: u32) -> Result<Latency, NetlinkError> {
+    measure(count, async || nl.get_link(interface, None).await).await
+}
+
+/// `version` over a single vici connection, answered by charon
+async fn bench_vici<C: ViciConnector>(connector: &C, count: u32) -> io::Result<Latency> {
+    let mut vici = connector.connect().await?;
+    measure(count, async || vici.version().await).await
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn _run(args: &BenchArgs, config: &Config) {
+    match bench_netlink(&Netlink::new().await, &args.interface, args.count).await {
+        Ok(latency) => println!("netlink get_link: {latency}"),
+        Err(e) => println!("netlink get_link failed: {e}"),
+    }
+
+    let connector = SocketConnector(config.vici_socket(), config.vici_timeouts());
+    match bench_vici(&connector, args.count).await {
+        Ok(latency) => println!("vici version: {latency}"),
+        Err(e) => println!("vici version failed: {e}"),
+    }
+}
+
+pub fn run(args: &BenchArgs, config: &Config) {
+    _run(args, config);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::vici::mock::{MockConnector, MockState};
+
+    #[tokio::test]
+    async fn test_measure() {
+        // a netlink request which takes a while
+        let mut calls = 0;
+        let latency = measure(3, async || {
+            calls += 1;
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            Ok::<_, NetlinkError>(())
+        })
+        .await
+        .unwrap();
+        assert_eq!(calls, 3);
+        assert!(latency.min >= Duration::from_millis(5));
+        assert!(latency.min <= latency.avg && latency.avg <= latency.max);
+
+        let r = measure(3, async || {
+            Err::<(), _>(NetlinkError::new("no such device"))
+        })
+        .await;
+        assert!(r.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_bench_vici() {
+        let latency = bench_vici(&MockConnector::default(), 3).await.unwrap();
+        assert!(latency.min <= latency.avg && latency.avg <= latency.max);
+
+        let connector = MockConnector(std::rc::Rc::new(std::cell::RefCell::new(MockState {
+            unreachable: true,
+            ..Default::default()
+        })));
+        assert!(bench_vici(&connector, 3).await.is_err());
+    }
+}
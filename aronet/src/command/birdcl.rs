@@ -17,6 +17,7 @@ pub fn run(args: &BirdclArgs, config: &Config) {
     let mut p = process::Command::new(config.birdcl_path().as_path());
     p.stdout(Stdio::inherit());
     p.stderr(Stdio::inherit());
+    p.arg("-s").arg(config.bird_socket_path());
 
     if let Some(a) = &args.args {
         p.args(a);
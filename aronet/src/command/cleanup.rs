@@ -0,0 +1,121 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+use clap::Args;
+use log::{info, warn};
+
+use crate::utils::configuration::Config;
+use crate::utils::netlink::Netlink;
+
+const NETNS_DIR: &str = "/var/run/netns";
+const PROC_DIR: &str = "/proc";
+
+#[derive(Args, Debug)]
+pub struct CleanupArgs {
+    /// only print stale netns without deleting them
+    #[arg(long)]
+    dry_run: bool,
+}
+
+/// device and inode of netns which processes are running in
+fn used_netns(proc_dir: &Path) -> HashSet<(u64, u64)> {
+    let Ok(entries) = fs::read_dir(proc_dir) else {
+        return HashSet::new();
+    };
+
+    entries
+        .flatten()
+        .filter(|entry| entry.file_name().to_string_lossy().parse::<u32>().is_ok())
+        // processes may exit or be inaccessible in the meantime
+        .filter_map(|entry| fs::metadata(entry.path().join("ns/net")).ok())
+        .map(|meta| (meta.dev(), meta.ino()))
+        .collect()
+}
+
+/// Names of netns under `netns_dir` starting with `prefix` and no process running in, sorted.
+fn stale_netns(
+    netns_dir: &Path,
+    prefix: &str,
+    used: &HashSet<(u64, u64)>,
+) -> io::Result<Vec<String>> {
+    let mut names = vec![];
+    for entry in fs::read_dir(netns_dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !name.starts_with(prefix) {
+            continue;
+        }
+
+        let meta = fs::metadata(entry.path())?;
+        if !used.contains(&(meta.dev(), meta.ino())) {
+            names.push(name);
+        }
+    }
+    names.sort();
+
+    Ok(names)
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn _run(args: &CleanupArgs, config: &Config) {
+    let used = used_netns(Path::new(PROC_DIR));
+    let stale = match stale_netns(Path::new(NETNS_DIR), &config.netns_name(), &used) {
+        Ok(stale) => stale,
+        Err(e) => {
+            warn!("failed to list netns under {NETNS_DIR}: {e}");
+            return;
+        }
+    };
+
+    if args.dry_run {
+        for name in &stale {
+            println!("{name}");
+        }
+        return;
+    }
+
+    let mut nl = Netlink::new().await;
+    for name in &stale {
+        info!("deleting netns {name}");
+        if let Err(e) = nl.delete_netns(name).await {
+            warn!("failed to delete netns {name}: {e}");
+        }
+    }
+}
+
+pub fn run(args: &CleanupArgs, config: &Config) {
+    _run(args, config);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_stale_netns() {
+        let dir = std::env::temp_dir().join(format!("aronet-netns-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["aronet", "aronet-old", "aronet-crashed", "other"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+
+        // a process is running in the netns of the daemon
+        let meta = fs::metadata(dir.join("aronet")).unwrap();
+        let used = HashSet::from([(meta.dev(), meta.ino())]);
+        let stale = stale_netns(&dir, "aronet", &used);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(stale.unwrap(), vec!["aronet-crashed", "aronet-old"]);
+        assert!(stale_netns(&dir, "aronet", &used).is_err());
+    }
+
+    #[test]
+    fn test_used_netns() {
+        // the netns of this process is always in use
+        let meta = fs::metadata("/proc/self/ns/net").unwrap();
+        assert!(used_netns(Path::new(PROC_DIR)).contains(&(meta.dev(), meta.ino())));
+    }
+}
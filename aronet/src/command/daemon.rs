@@ -1,18 +1,35 @@
-use crate::daemon::{Daemon, bird::Bird, strongswan::Strongswan};
-use crate::utils::IpNetwork;
-use crate::utils::configuration::{Config, DaemonMode, Registries};
-use crate::utils::netlink::Netlink;
+use crate::daemon::{
+    Daemon,
+    bird::{Bird, rtt_to_cost},
+    strongswan::{Strongswan, check_max_peers, plan_connections},
+};
+use crate::utils::configuration::{Config, DaemonMode, Registries, RoutingProtocol, StaticRoute};
+use crate::utils::netlink::{Netlink, NetlinkError, RouteSpec};
+use crate::utils::{AddressFamily, IpNetwork};
 use clap::{Args, ValueEnum};
 use futures::join;
 use log::{info, warn};
+use netlink_packet_route::route::RouteScope;
+use serde::Serialize;
 use std::cell::{RefCell, RefMut};
 use std::io::ErrorKind;
 use std::net::IpAddr;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
 use std::rc::Rc;
 use std::str::FromStr;
 use tokio::signal::unix::{SignalKind, signal};
+use tokio::time::{Duration, sleep};
 use tokio_util::sync::CancellationToken;
 
+/// delay of reconfiguring bird after an RTT is measured, so that SAs coming up together are
+/// applied at once
+const RTT_COST_DELAY: Duration = Duration::from_secs(5);
+
+/// priority of the ip rule selecting packets with `fwmark` into the table of the vrf, in the
+/// root netns
+const FWMARK_RULE_PRIORITY: u32 = 100;
+
 #[derive(Debug, Args)]
 pub struct DaemonArgs {
     #[arg(value_enum)]
@@ -36,13 +53,16 @@ struct DaemonState<'a> {
 
 impl<'a> DaemonState<'a> {
     async fn new(config: &'a Config, registries: &'a Registries, token: CancellationToken) -> Self {
-        let nl = Rc::new(RefCell::new(Netlink::new().await));
+        let mut nl = Netlink::new().await;
+        // an xfrm interface kept by make-before-break is reused by the new SA
+        nl.set_idempotent(true);
+        let nl = Rc::new(RefCell::new(nl));
 
         Self {
             config,
             registries,
             strongswan: Strongswan::new(config, registries, token.clone(), Rc::clone(&nl)),
-            bird: Bird::new(config, token.clone()),
+            bird: Bird::new(config, registries, token.clone()),
             cancel_token: token,
             netlink: nl,
         }
@@ -68,6 +88,25 @@ impl<'a> DaemonState<'a> {
         let mut nl = netlink.borrow_mut();
 
         info!("cleanup netlink resources of daemon...");
+        // routes through other interfaces aren't deleted along with the main interface
+        for route in &self.config.daemon.static_routes {
+            if let Err(err) = delete_static_route(&nl, self.config, route).await
+                && !err.is_netlink_not_found()
+                && !err.is_netlink_no_route()
+            {
+                warn!("failed to delete static route {}: {err}", route.destination);
+            }
+        }
+        if let Some(mark) = self.config.daemon.fwmark {
+            for family in [AddressFamily::Ip4, AddressFamily::Ip6] {
+                let r = nl
+                    .delete_rule(self.config.route_table(), Some(mark), family)
+                    .await;
+                if let Err(err) = r {
+                    warn!("failed to delete fwmark rule: {err}");
+                }
+            }
+        }
         if self.config.daemon.mode == DaemonMode::Netns {
             info!("trying to delete netns");
             if let Err(err) = nl.delete_netns(&self.config.netns_name()).await {
@@ -83,6 +122,74 @@ impl<'a> DaemonState<'a> {
                 }
             }
         }
+
+        let path = self.config.created_netns_state_path();
+        if let Ok(created) = tokio::fs::read_to_string(&path).await {
+            for netns in created.lines().filter(|l| !l.is_empty()) {
+                info!("trying to delete netns {netns}");
+                if let Err(err) = nl.delete_netns(netns).await
+                    && !err.is_netlink_not_found()
+                {
+                    warn!("failed to delete netns {netns}: {err}");
+                }
+            }
+            if let Err(e) = tokio::fs::remove_file(&path).await {
+                warn!("failed to remove {}: {e}", path.display());
+            }
+        }
+    }
+
+    /// Install routes of other nodes after the first IKE_SA is up, if they were deferred.
+    async fn install_deferred_routes(&self) {
+        if self.config.daemon.mode == DaemonMode::Dummy || !self.config.daemon.defer_peer_routes {
+            return;
+        }
+
+        let install = async {
+            info!("installing routes of other nodes after the first SA is up");
+            let nl = self.netlink.borrow();
+            for net in peer_routes(self.config, self.registries) {
+                if let Err(e) = create_peer_route(&nl, self.config, net).await {
+                    warn!("failed to create route {net}: {e}");
+                }
+            }
+            // reconcile adds routes of other nodes from now on
+            if let Err(e) = tokio::fs::write(self.config.peer_routes_state_path(), "").await {
+                warn!("failed to record routes of other nodes as installed: {e}");
+            }
+        };
+        after_sa_up(self.strongswan.wait_sa_up(), &self.cancel_token, install).await;
+    }
+
+    /// Feed the cost of the measured RTT of each interface into bird, measurements in a row
+    /// are applied by a single reconfiguration.
+    async fn apply_rtt_costs(&self) {
+        if !self.config.daemon.rtt_cost
+            || self.config.daemon.routing_protocol != RoutingProtocol::Babel
+        {
+            return;
+        }
+
+        loop {
+            tokio::select! {
+                _ = async {
+                    self.strongswan.wait_rtt_measured().await;
+                    sleep(RTT_COST_DELAY).await;
+                } => {}
+                _ = self.cancel_token.cancelled() => break,
+            }
+
+            let costs = self
+                .strongswan
+                .interface_rtts()
+                .into_iter()
+                .map(|(name, rtt)| (name, rtt_to_cost(rtt)))
+                .collect();
+            self.bird.set_interface_costs(costs);
+            if let Err(e) = self.bird.reconfigure().await {
+                warn!("failed to apply costs of RTT to bird: {e}");
+            }
+        }
     }
 
     pub async fn start(&mut self) {
@@ -93,6 +200,8 @@ impl<'a> DaemonState<'a> {
         join!(
             self.strongswan.runner(),
             self.bird.runner(),
+            self.install_deferred_routes(),
+            self.apply_rtt_costs(),
             self.handle_signals()
         );
 
@@ -104,33 +213,42 @@ impl<'a> DaemonState<'a> {
         let netlink = Rc::clone(&self.netlink);
         let mut nl = netlink.borrow_mut();
 
-        // swanctl is under runtime_dir, so this also creates runtime_dir
-        tokio::fs::create_dir_all(self.config.swanctl_conf_dir().as_path())
-            .await
-            .unwrap_or_else(|err| {
-                if err.kind() != ErrorKind::AlreadyExists {
-                    panic!(
-                        "failed to create directory {:?}: {err:?}",
-                        self.config.swanctl_conf_dir().as_path()
-                    );
-                }
-            });
-
-        let mut if_ips: Vec<crate::utils::IpNetwork> = vec![self.config.main_network()];
-        if let Some(extra_ips) = self.config.daemon.extra_ip.as_ref() {
-            for i in extra_ips {
-                if_ips.push(*i);
-            }
+        // swanctl is under config_gen_dir, so this also creates config_gen_dir
+        for dir in [
+            self.config.runtime_dir(),
+            self.config.socket_dir(),
+            self.config.swanctl_conf_dir(),
+        ] {
+            tokio::fs::create_dir_all(dir.as_path())
+                .await
+                .unwrap_or_else(|err| {
+                    if err.kind() != ErrorKind::AlreadyExists {
+                        panic!("failed to create directory {:?}: {err:?}", dir.as_path());
+                    }
+                });
         }
 
+        let if_ips = main_addresses(self.config);
+        let mut report = SetupReport::new(self.config);
+
         info!("creating main interface {}", self.config.ifname());
         match self.config.daemon.mode {
             // in netns mode, the main interface is a veth pair
             crate::utils::configuration::DaemonMode::Netns => {
                 info!("creating netns {}", self.config.netns_name());
-                nl.create_netns(&self.config.netns_name())
+                let created = nl
+                    .create_netns(&self.config.netns_name())
                     .await
                     .expect("failed to create netns");
+                if created {
+                    report.netns.push(self.config.netns_name());
+                }
+                if let Some(resolv_conf) = &self.config.daemon.netns_resolv_conf {
+                    nl.mount_resolv_conf(&self.config.netns_name(), Path::new(resolv_conf))
+                        .await
+                        .map_err(|e| format!("{e}"))
+                        .expect("failed to mount resolv.conf of netns");
+                }
 
                 nl.create_veth(
                     self.config.ifname(),
@@ -142,80 +260,400 @@ impl<'a> DaemonState<'a> {
                 .await
                 .map_err(|e| format!("{e}"))
                 .expect("cannot create veth");
+                report.interface(self.config.ifname(), &if_ips);
+                report.peer_address = Some(self.config.peer_network());
 
                 // direct traffic out of netns
                 nl.create_route(
                     IpNetwork::from_str("::/0").unwrap(),
                     self.config.ifname(),
-                    None,
-                    None,
-                    None,
-                    None,
-                    None,
-                    Some(&self.config.netns_name()),
+                    RouteSpec {
+                        // there is no gateway, the default route is only valid on the veth
+                        scope: Some(RouteScope::Link),
+                        netns: Some(&self.config.netns_name()),
+                        ..Default::default()
+                    },
                 )
                 .await
                 .map_err(|e| format!("{e}"))
                 .expect("creating default route for ipv6 in netns failed");
+                let netns = Some(self.config.netns_name());
+                report.route(
+                    IpNetwork::from_str("::/0").unwrap(),
+                    self.config.ifname(),
+                    None,
+                    netns.clone(),
+                );
 
-                nl.create_route(
+                let gateway = create_netns_v4_route(&nl, self.config)
+                    .await
+                    .expect("creating default route for ipv4 in netns failed");
+                report.route(
                     IpNetwork::from_str("0.0.0.0/0").unwrap(),
                     self.config.ifname(),
-                    Some(self.config.main_network().ip),
-                    None,
-                    None,
-                    None,
-                    None,
-                    Some(&self.config.netns_name()),
-                )
-                .await
-                .expect("creating default route for ipv4 in netns failed");
+                    gateway,
+                    netns,
+                );
             }
             crate::utils::configuration::DaemonMode::Vrf => {
                 // in vrf mode, the main interface is a vrf device
-                nl.create_vrf(self.config.ifname(), self.config.route_table(), if_ips)
+                if !nl.supports_vrf().await.unwrap_or(true) {
+                    panic!("vrf isn't supported by the kernel, consider netns mode instead");
+                }
+                nl.create_vrf(
+                    self.config.ifname(),
+                    self.config.route_table(),
+                    if_ips.clone(),
+                )
+                .await
+                .map_err(|e| format!("failed to create vrf {}: {e}", self.config.ifname()))
+                .unwrap();
+                report.interface(self.config.ifname(), &if_ips);
+            }
+            crate::utils::configuration::DaemonMode::Dummy => {
+                // in dummy mode, the main interface only holds addresses, routes of other nodes
+                // are exported to main table by bird directly
+                nl.create_dummy(self.config.ifname(), if_ips.clone())
                     .await
-                    .map_err(|e| format!("failed to create vrf {}: {e}", self.config.ifname()))
+                    .map_err(|e| format!("failed to create dummy {}: {e}", self.config.ifname()))
                     .unwrap();
+                report.interface(self.config.ifname(), &if_ips);
             }
         }
 
-        // common things for netns and vrf mode
-        let local_name = format!("{}-{}", self.config.organization, self.config.common_name);
-        let mut gateway: Option<IpAddr> = None;
-        if self.config.daemon.mode == DaemonMode::Netns {
-            gateway = Some(self.config.peer_network().ip);
+        // netns of charon may be given explicitly, it is reused if existing and only deleted on
+        // cleanup if created here
+        if let Some(netns) = self.config.daemon.charon_netns.as_ref() {
+            let created = nl
+                .create_netns(netns)
+                .await
+                .map_err(|e| format!("failed to create netns {netns}: {e}"))
+                .unwrap();
+            if created && *netns != self.config.netns_name() {
+                report.netns.push(netns.clone());
+                let path = self.config.created_netns_state_path();
+                if let Err(e) = tokio::fs::write(&path, format!("{netns}\n")).await {
+                    warn!("failed to record netns {netns} as created: {e}");
+                }
+            }
         }
-        for registry in self.registries {
-            for node in &registry.nodes {
-                let remote_name = format!("{}-{}", registry.organization, node.common_name);
 
-                if local_name == remote_name {
-                    continue;
-                }
+        let alias = format!("{}:main", self.config.ifname());
+        if let Err(e) = nl.set_link_alias(self.config.ifname(), &alias, None).await {
+            warn!("failed to set alias of main interface: {e}");
+        }
+        if let Some(len) = self.config.daemon.txqueuelen
+            && let Err(e) = nl.set_link_txqlen(self.config.ifname(), len, None).await
+        {
+            warn!("failed to set txqueuelen of main interface: {e}");
+        }
 
-                let mut networks = node.remarks.extra_network.clone();
-                networks.push(node.remarks.network);
-                for net in networks {
-                    nl.create_route(
-                        net,
-                        self.config.ifname(),
-                        gateway,
-                        None,
-                        None,
-                        None,
-                        None,
-                        None,
-                    )
+        // routes of other nodes through the main interface in netns and vrf mode, bird exports
+        // them in dummy mode. They are installed after the first SA if deferred
+        if self.config.daemon.mode != DaemonMode::Dummy && !self.config.daemon.defer_peer_routes {
+            let gateway = peer_route_gateway(self.config);
+            for net in peer_routes(self.config, self.registries) {
+                create_peer_route(&nl, self.config, net)
                     .await
                     .map_err(|e| format!("{e}"))
                     .expect("creating route failed");
+                report.route(net, self.config.ifname(), gateway, None);
+            }
+        }
+
+        for route in &self.config.daemon.static_routes {
+            let dev = static_route_dev(self.config, route);
+            match create_static_route(&nl, self.config, route).await {
+                Ok(_) => report.route(route.destination, dev, route.via, None),
+                // route through another interface is left over from previous run
+                Err(e) if e.is_netlink_exist() => {}
+                Err(e) => panic!("creating static route {} failed: {e}", route.destination),
+            }
+        }
+
+        if let Some(mark) = self.config.daemon.fwmark {
+            for family in [AddressFamily::Ip4, AddressFamily::Ip6] {
+                // a rule left over from previous run is kept
+                match nl
+                    .create_rule(
+                        FWMARK_RULE_PRIORITY,
+                        self.config.route_table(),
+                        Some(mark),
+                        family,
+                    )
+                    .await
+                {
+                    Ok(_) => report.fwmark = Some(mark),
+                    Err(e) if e.is_netlink_exist() => {}
+                    Err(e) => warn!("failed to create fwmark rule: {e}"),
                 }
             }
         }
+
+        let table = self.config.route_table().to_string();
+        if let Err(e) = tokio::fs::write(self.config.route_table_state_path(), table).await {
+            warn!("failed to record route table in use: {e}");
+        }
+        // left by a previous run, routes of other nodes are deferred again
+        if let Err(e) = tokio::fs::remove_file(self.config.peer_routes_state_path()).await
+            && e.kind() != ErrorKind::NotFound
+        {
+            warn!(
+                "failed to remove {}: {e}",
+                self.config.peer_routes_state_path().display()
+            );
+        }
+
+        if let Some(path) = self.config.daemon.setup_report.as_ref() {
+            let r = tokio::fs::write(path, serde_json::to_vec_pretty(&report).unwrap()).await;
+            if let Err(e) = r {
+                warn!("failed to write setup report to {path}: {e}");
+            }
+        }
+    }
+}
+
+/// addresses of the main interface
+fn main_addresses(config: &Config) -> Vec<IpNetwork> {
+    let mut addresses = vec![config.main_network()];
+    addresses.extend(config.daemon.extra_ip.iter().flatten().copied());
+
+    addresses
+}
+
+/// a route created by `setup`
+#[derive(Debug, Serialize)]
+struct ReportedRoute {
+    dest: IpNetwork,
+    dev: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    gateway: Option<IpAddr>,
+    /// in the root netns if not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    netns: Option<String>,
+}
+
+/// What `setup` provisioned in this run, recorded as it is created and written to
+/// `setup_report` for external tooling. Resources left over from a previous run aren't listed.
+#[derive(Debug, Serialize)]
+struct SetupReport {
+    mode: DaemonMode,
+    interface: Option<String>,
+    netns: Vec<String>,
+    addresses: Vec<IpNetwork>,
+    /// address of the veth end in netns, only in netns mode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    peer_address: Option<IpNetwork>,
+    routes: Vec<ReportedRoute>,
+    route_table: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fwmark: Option<u32>,
+}
+
+impl SetupReport {
+    fn new(config: &Config) -> Self {
+        SetupReport {
+            mode: config.daemon.mode,
+            interface: None,
+            netns: vec![],
+            addresses: vec![],
+            peer_address: None,
+            routes: vec![],
+            route_table: config.route_table(),
+            fwmark: None,
+        }
+    }
+
+    fn interface(&mut self, name: &str, addresses: &[IpNetwork]) {
+        self.interface = Some(name.to_string());
+        self.addresses = addresses.to_vec();
+    }
+
+    fn route(
+        &mut self,
+        dest: IpNetwork,
+        dev: &str,
+        gateway: Option<IpAddr>,
+        netns: Option<String>,
+    ) {
+        self.routes.push(ReportedRoute {
+            dest: IpNetwork {
+                ip: dest.formatted_ip(),
+                mask: dest.mask,
+            },
+            dev: dev.to_string(),
+            gateway,
+            netns,
+        });
     }
 }
 
+/// Run `then` once `sa_up` resolves, nothing is run if the daemon is stopped first.
+async fn after_sa_up<U: Future, T: Future>(sa_up: U, cancel_token: &CancellationToken, then: T) {
+    tokio::select! {
+        _ = sa_up => {
+            then.await;
+        }
+        _ = cancel_token.cancelled() => {}
+    }
+}
+
+/// Default route for ipv4 in netns, via the main address (ipv4 nexthop via ipv6) unless
+/// `netns_v4_onlink` is set or the main address is unreachable. Returns the gateway of the
+/// created route.
+async fn create_netns_v4_route(
+    nl: &Netlink,
+    config: &Config,
+) -> Result<Option<IpAddr>, NetlinkError> {
+    let dest = IpNetwork::from_str("0.0.0.0/0").unwrap();
+    let netns = config.netns_name();
+
+    if !config.daemon.netns_v4_onlink {
+        let gateway = config.main_network().ip;
+        let r = nl
+            .create_route(
+                dest,
+                config.ifname(),
+                RouteSpec {
+                    gateway: Some(gateway),
+                    netns: Some(&netns),
+                    ..Default::default()
+                },
+            )
+            .await;
+        match r {
+            Err(e) if e.is_netlink_unreachable() => {
+                warn!("gateway {gateway} is unreachable, default route for ipv4 is on-link: {e}")
+            }
+            r => return r.map(|_| Some(gateway)),
+        }
+    }
+
+    nl.create_route(
+        dest,
+        config.ifname(),
+        RouteSpec {
+            scope: Some(RouteScope::Link),
+            netns: Some(&netns),
+            ..Default::default()
+        },
+    )
+    .await
+    .map(|_| None)
+}
+
+/// gateway of routes of other nodes, the peer end of the veth in netns mode
+fn peer_route_gateway(config: &Config) -> Option<IpAddr> {
+    (config.daemon.mode == DaemonMode::Netns).then(|| config.peer_network().ip)
+}
+
+/// Route a network of other node through the main interface, in the main table.
+pub(super) async fn create_peer_route(
+    nl: &Netlink,
+    config: &Config,
+    net: IpNetwork,
+) -> Result<(), NetlinkError> {
+    let gateway = peer_route_gateway(config);
+    // traffic originated from this node uses its overlay address
+    let main_ip = config.main_network().ip;
+    let prefsrc = (net.ip.is_ipv4() == main_ip.is_ipv4()).then_some(main_ip);
+
+    nl.create_route(
+        net,
+        config.ifname(),
+        RouteSpec {
+            gateway,
+            prefsrc,
+            ..Default::default()
+        },
+    )
+    .await
+}
+
+fn static_route_dev<'a>(config: &'a Config, route: &'a StaticRoute) -> &'a str {
+    route.dev.as_deref().unwrap_or(config.ifname())
+}
+
+/// Table of `static_routes`, the overlay table of the vrf in vrf mode and the main table
+/// otherwise.
+fn static_route_table(config: &Config) -> u32 {
+    match config.daemon.mode {
+        DaemonMode::Vrf => config.route_table(),
+        _ => 254,
+    }
+}
+
+/// Routes of `static_routes` through the main interface in the main table, which share it with
+/// routes of other nodes.
+pub(super) fn main_static_routes(config: &Config) -> impl Iterator<Item = &StaticRoute> {
+    config.daemon.static_routes.iter().filter(|r| {
+        static_route_table(config) == 254 && static_route_dev(config, r) == config.ifname()
+    })
+}
+
+/// Install a route of `static_routes` into `static_route_table`.
+pub(super) async fn create_static_route(
+    nl: &Netlink,
+    config: &Config,
+    route: &StaticRoute,
+) -> Result<(), NetlinkError> {
+    nl.create_route(
+        route.destination,
+        static_route_dev(config, route),
+        RouteSpec {
+            gateway: route.via,
+            table: Some(static_route_table(config)),
+            ..Default::default()
+        },
+    )
+    .await
+}
+
+async fn delete_static_route(
+    nl: &Netlink,
+    config: &Config,
+    route: &StaticRoute,
+) -> Result<(), NetlinkError> {
+    nl.delete_route(
+        route.destination,
+        static_route_dev(config, route),
+        static_route_table(config),
+        None,
+    )
+    .await
+}
+
+/// Networks of other nodes in registries, which are routed through the main interface.
+pub(super) fn peer_routes(config: &Config, registries: &Registries) -> Vec<IpNetwork> {
+    let mut routes = vec![];
+
+    for (_, node) in config.peers(registries) {
+        routes.extend(node.remarks.extra_network());
+        routes.push(node.remarks.network);
+    }
+
+    routes
+}
+
+/// Check the binary exists and is executable, so that the resolved path is reported instead of
+/// failing on launch.
+fn check_executable(name: &str, path: &Path) -> Result<(), String> {
+    let metadata = std::fs::metadata(path)
+        .map_err(|e| format!("{name} was not found at {}: {e}", path.display()))?;
+
+    if !metadata.is_file() || metadata.permissions().mode() & 0o111 == 0 {
+        return Err(format!("{name} at {} is not executable", path.display()));
+    }
+
+    Ok(())
+}
+
+fn check_binaries(config: &Config) -> Result<(), String> {
+    check_executable("charon", &config.charon_path())?;
+    check_executable("bird", &config.bird_path())?;
+    check_executable("swanctl", &config.swanctl_path())
+}
+
 #[tokio::main(flavor = "current_thread")]
 async fn _run(args: &DaemonArgs, config: &Config, registries: &Registries) {
     let token = CancellationToken::new();
@@ -233,5 +671,344 @@ async fn _run(args: &DaemonArgs, config: &Config, registries: &Registries) {
 }
 
 pub fn run(args: &DaemonArgs, config: &Config, registries: &Registries) {
+    if let Err(e) = check_binaries(config) {
+        panic!("{e}");
+    }
+    if let Err(e) = config.validate_extra_ip() {
+        panic!("{e}");
+    }
+    if let Err(e) = check_max_peers(config, &plan_connections(config, registries)) {
+        panic!("{e}");
+    }
+    let mismatches = config.self_entry_mismatches(registries);
+    for mismatch in &mismatches {
+        warn!("entry of this node in registry differs from config: {mismatch}");
+    }
+    if config.daemon.strict_self_entry && !mismatches.is_empty() {
+        panic!("entry of this node in registry differs from config");
+    }
+
     _run(args, config, registries);
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_after_sa_up() {
+        let sa_up = tokio::sync::Notify::new();
+        let installed = std::cell::Cell::new(false);
+        let token = CancellationToken::new();
+
+        let deferred = after_sa_up(sa_up.notified(), &token, async { installed.set(true) });
+        let up = async {
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            // nothing is installed before the first SA
+            assert!(!installed.get());
+            sa_up.notify_one();
+        };
+        join!(deferred, up);
+        assert!(installed.get());
+
+        let installed = std::cell::Cell::new(false);
+        token.cancel();
+        after_sa_up(std::future::pending::<()>(), &token, async {
+            installed.set(true)
+        })
+        .await;
+        assert!(!installed.get());
+    }
+
+    fn config(daemon: &str) -> Config {
+        serde_json::from_str(&format!(
+            r#"{{
+                "private_key": "",
+                "organization": "example",
+                "common_name": "local",
+                "daemon": {daemon},
+                "endpoints": []
+            }}"#
+        ))
+        .unwrap()
+    }
+
+    fn registries() -> Registries {
+        serde_json::from_str(
+            r#"[{
+                "public_key": "",
+                "organization": "example",
+                "nodes": [
+                    {
+                        "common_name": "local",
+                        "endpoints": [],
+                        "remarks": { "network": "fd00::1/64" }
+                    },
+                    {
+                        "common_name": "remote",
+                        "endpoints": [],
+                        "remarks": { "network": "fd01::1/64", "extra_network": ["10.0.1.0/24"] }
+                    },
+                    {
+                        "common_name": "other",
+                        "endpoints": [],
+                        "remarks": { "network": "fd02::1/64" }
+                    }
+                ]
+            }]"#,
+        )
+        .unwrap()
+    }
+
+    fn routes(config: &Config, registries: &Registries) -> Vec<String> {
+        peer_routes(config, registries)
+            .iter()
+            .map(|n| n.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_peer_routes() {
+        let c = config(r#"{ "network": "fd00::1/64" }"#);
+        assert_eq!(
+            routes(&c, &registries()),
+            vec!["10.0.1.0/24", "fd01::/64", "fd02::/64"]
+        );
+    }
+
+    #[test]
+    fn test_peer_routes_exclude_peers() {
+        let c = config(r#"{ "network": "fd00::1/64", "exclude_peers": ["O=example,CN=remote"] }"#);
+        assert_eq!(routes(&c, &registries()), vec!["fd02::/64"]);
+    }
+
+    #[test]
+    fn test_peer_routes_disabled() {
+        let c = config(r#"{ "network": "fd00::1/64" }"#);
+        let mut registries = registries();
+        registries[0].nodes[1].disabled = true;
+        assert_eq!(routes(&c, &registries), vec!["fd02::/64"]);
+    }
+
+    #[test]
+    fn test_setup_report() {
+        let c = config(
+            r#"{
+                "network": "fd00::1/64",
+                "extra_ip": ["10.0.0.1/24"],
+                "mode": "netns",
+                "ifname": "aronet",
+                "route_table": 128
+            }"#,
+        );
+        // nothing is listed until setup creates it
+        let mut report = SetupReport::new(&c);
+        assert_eq!(
+            serde_json::to_value(&report).unwrap(),
+            serde_json::json!({
+                "mode": "netns",
+                "interface": null,
+                "netns": [],
+                "addresses": [],
+                "routes": [],
+                "route_table": 128
+            })
+        );
+
+        report.netns.push(c.netns_name());
+        report.interface(c.ifname(), &main_addresses(&c));
+        report.peer_address = Some(c.peer_network());
+        let netns = Some(c.netns_name());
+        report.route(
+            IpNetwork::from_str("0.0.0.0/0").unwrap(),
+            c.ifname(),
+            None,
+            netns,
+        );
+        let gateway = peer_route_gateway(&c);
+        for net in peer_routes(&c, &registries()) {
+            report.route(net, c.ifname(), gateway, None);
+        }
+        report.route(
+            IpNetwork::from_str("fd10::/48").unwrap(),
+            "eth1",
+            None,
+            None,
+        );
+        assert_eq!(
+            serde_json::to_value(&report).unwrap(),
+            serde_json::json!({
+                "mode": "netns",
+                "interface": "aronet",
+                "netns": ["aronet"],
+                "addresses": ["fd00::1/64", "10.0.0.1/24"],
+                "peer_address": "fd00::2/64",
+                "routes": [
+                    { "dest": "0.0.0.0/0", "dev": "aronet", "netns": "aronet" },
+                    { "dest": "10.0.1.0/24", "dev": "aronet", "gateway": "fd00::2" },
+                    { "dest": "fd01::/64", "dev": "aronet", "gateway": "fd00::2" },
+                    { "dest": "fd02::/64", "dev": "aronet", "gateway": "fd00::2" },
+                    { "dest": "fd10::/48", "dev": "eth1" }
+                ],
+                "route_table": 128
+            })
+        );
+
+        let c = config(r#"{ "network": "fd00::1/64", "mode": "vrf" }"#);
+        assert_eq!(peer_route_gateway(&c), None);
+    }
+
+    #[test]
+    fn test_static_routes() {
+        let c = config(
+            r#"{
+                "network": "fd00::1/64",
+                "ifname": "aronet",
+                "static_routes": [
+                    { "destination": "192.0.2.0/24", "via": "fd01::1" },
+                    { "destination": "fd10::/48", "dev": "eth1" }
+                ]
+            }"#,
+        );
+        assert_eq!(static_route_dev(&c, &c.daemon.static_routes[1]), "eth1");
+        assert_eq!(static_route_dev(&c, &c.daemon.static_routes[0]), "aronet");
+    }
+
+    #[tokio::test]
+    #[ignore = "requires CAP_NET_ADMIN"]
+    async fn create_static_route_gateway() {
+        let c = config(
+            r#"{
+                "network": "fd00:9::1/64",
+                "mode": "netns",
+                "ifname": "aronet-static",
+                "static_routes": [{ "destination": "fd00:a::/64", "via": "fd00:9::2" }]
+            }"#,
+        );
+        let mut nl = Netlink::new().await;
+        nl.create_netns("aronet-test-static").await.unwrap();
+        nl.create_veth(
+            c.ifname(),
+            c.ifname(),
+            Some("aronet-test-static"),
+            Some(&vec![c.main_network()]),
+            None,
+        )
+        .await
+        .unwrap();
+
+        let r = create_static_route(&nl, &c, &c.daemon.static_routes[0]).await;
+        let index = nl.get_link(c.ifname(), None).await.unwrap().header.index;
+        let routes = nl.list_routes(254, None).await.unwrap();
+        nl.delete_netns("aronet-test-static").await.unwrap();
+        r.unwrap();
+
+        let route = routes
+            .into_iter()
+            .find(|r| r.dest == IpNetwork::from_str("fd00:a::/64").unwrap())
+            .expect("static route not found");
+        assert_eq!(route.gateway, Some(IpAddr::from_str("fd00:9::2").unwrap()));
+        assert_eq!(route.oif, Some(index));
+    }
+
+    #[test]
+    fn test_main_static_routes() {
+        let c = config(
+            r#"{
+                "network": "fd00::1/64",
+                "mode": "netns",
+                "ifname": "aronet",
+                "static_routes": [
+                    { "destination": "192.0.2.0/24", "via": "fd01::1" },
+                    { "destination": "fd10::/48", "dev": "eth1" },
+                    { "destination": "fd11::/48", "dev": "aronet" }
+                ]
+            }"#,
+        );
+        let routes: Vec<String> = main_static_routes(&c)
+            .map(|r| r.destination.to_string())
+            .collect();
+        assert_eq!(routes, vec!["192.0.2.0/24", "fd11::/48"]);
+        assert_eq!(static_route_table(&c), 254);
+
+        // in the table of the vrf, which isn't reconciled with the main table
+        let mut c = c;
+        c.daemon.mode = DaemonMode::Vrf;
+        assert_eq!(static_route_table(&c), 128);
+        assert_eq!(main_static_routes(&c).count(), 0);
+    }
+
+    async fn netns_v4_gateway(onlink: bool) -> Option<IpAddr> {
+        let c = config(&format!(
+            r#"{{
+                "network": "fd00:8::1/64",
+                "mode": "netns",
+                "ifname": "aronet-v4",
+                "netns_name": "aronet-test-v4",
+                "netns_v4_onlink": {onlink}
+            }}"#
+        ));
+        let mut nl = Netlink::new().await;
+        nl.create_netns(&c.netns_name()).await.unwrap();
+        nl.create_veth(
+            c.ifname(),
+            c.ifname(),
+            Some(&c.netns_name()),
+            Some(&vec![c.main_network()]),
+            Some(&vec![c.peer_network()]),
+        )
+        .await
+        .unwrap();
+
+        let r = create_netns_v4_route(&nl, &c).await;
+        let routes = nl.list_routes(254, Some(&c.netns_name())).await.unwrap();
+        nl.delete_netns(&c.netns_name()).await.unwrap();
+
+        let gateway = routes
+            .into_iter()
+            .find(|r| r.dest == IpNetwork::from_str("0.0.0.0/0").unwrap())
+            .expect("default route for ipv4 not found")
+            .gateway;
+        // reported as created
+        assert_eq!(r.unwrap(), gateway);
+        gateway
+    }
+
+    #[tokio::test]
+    #[ignore = "requires CAP_NET_ADMIN"]
+    async fn create_netns_v4_route_gateway() {
+        assert_eq!(
+            netns_v4_gateway(false).await,
+            Some(IpAddr::from_str("fd00:8::1").unwrap())
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "requires CAP_NET_ADMIN"]
+    async fn create_netns_v4_route_onlink() {
+        assert_eq!(netns_v4_gateway(true).await, None);
+    }
+
+    #[test]
+    fn test_check_executable() {
+        let dir = std::env::temp_dir().join(format!("aronet-binaries-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let charon = dir.join("charon");
+        std::fs::write(&charon, "").unwrap();
+
+        std::fs::set_permissions(&charon, std::fs::Permissions::from_mode(0o644)).unwrap();
+        let not_executable = check_executable("charon", &charon);
+        std::fs::set_permissions(&charon, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let present = check_executable("charon", &charon);
+        let missing = check_executable("bird", &dir.join("bird"));
+        let directory = check_executable("bird", &dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(present.is_ok());
+        assert!(not_executable.unwrap_err().contains("not executable"));
+        let missing = missing.unwrap_err();
+        assert!(missing.starts_with("bird was not found at "), "{missing}");
+        assert!(missing.contains(&dir.join("bird").display().to_string()));
+        assert!(directory.is_err());
+    }
+}
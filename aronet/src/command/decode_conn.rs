@@ -0,0 +1,26 @@
+use clap::Args;
+
+use crate::daemon::strongswan::decode_connection_name;
+
+#[derive(Args, Debug)]
+pub struct DecodeConnArgs {
+    /// name of a connection as shown by charon or swanctl
+    name: String,
+}
+
+pub fn run(args: &DecodeConnArgs) {
+    match decode_connection_name(&args.name) {
+        Ok((local, remote)) => {
+            for (side, id) in [("local", local), ("remote", remote)] {
+                println!(
+                    "{side}: organization={} common_name={} serial_number={}",
+                    id.organization, id.common_name, id.serial_number
+                );
+            }
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::Args;
+use log::info;
+use serde::Serialize;
+use serde_json::{Value, json};
+
+use crate::utils::configuration::Config;
+use crate::utils::netlink::Netlink;
+use crate::utils::vici::{SocketConnector, ViciApi, ViciConnector};
+
+/// number of lines kept from the end of the event log
+const RECENT_EVENTS: usize = 100;
+
+#[derive(Args, Debug)]
+pub struct DiagArgs {
+    /// file which the bundle is written to
+    #[arg(short, long, default_value = "aronet-diag.json")]
+    output: PathBuf,
+}
+
+/// the value, or the error in place of it so that a failed query doesn't drop the others
+fn section<T: Serialize, E: ToString>(r: Result<T, E>) -> Value {
+    match r {
+        Ok(v) => serde_json::to_value(v).unwrap(),
+        Err(e) => json!({ "error": e.to_string() }),
+    }
+}
+
+/// responses of read-only vici requests, counters are the global ones
+async fn charon_state<C: ViciConnector>(connector: &C) -> Value {
+    let mut vici = match connector.connect().await {
+        Ok(vici) => vici,
+        Err(e) => return section::<(), _>(Err(e)),
+    };
+
+    json!({
+        "version": section(vici.version().await),
+        "stats": section(vici.stats().await),
+        "sas": section(vici.list_sas().await),
+        "conns": section(vici.get_conns().await),
+        "counters": section(vici.get_counters(None).await),
+    })
+}
+
+/// content of configs generated for charon and bird
+fn generated_configs(config: &Config) -> Value {
+    let mut configs = serde_json::Map::new();
+    for path in [config.strongswan_config_path(), config.bird_conf_path()] {
+        configs.insert(
+            path.display().to_string(),
+            section(fs::read_to_string(&path)),
+        );
+    }
+
+    Value::Object(configs)
+}
+
+/// last lines of the event log, including the failed connections
+fn recent_events(path: Option<&Path>) -> Value {
+    let Some(path) = path else {
+        return json!([]);
+    };
+
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) => return section::<(), _>(Err(e)),
+    };
+    let lines: Vec<&str> = content.lines().collect();
+    let recent = &lines[lines.len().saturating_sub(RECENT_EVENTS)..];
+
+    Value::Array(
+        recent
+            .iter()
+            .map(|line| serde_json::from_str(line).unwrap_or(Value::String(line.to_string())))
+            .collect(),
+    )
+}
+
+/// interfaces of aronet in the netns of bird with their routes in the route table
+async fn interfaces(nl: &Netlink, config: &Config) -> Value {
+    let netns = config.bird_netns();
+    let links = match nl.list_links(config.ifname(), netns.as_deref()).await {
+        Ok(links) => links,
+        Err(e) => return section::<(), _>(Err(e)),
+    };
+    let routes = match nl.list_routes(config.route_table(), netns.as_deref()).await {
+        Ok(routes) => routes,
+        Err(e) => return section::<(), _>(Err(e)),
+    };
+
+    links
+        .into_iter()
+        .map(|(index, name)| {
+            let routes: Vec<String> = routes
+                .iter()
+                .filter(|r| r.oif == Some(index))
+                .map(|r| r.dest.to_string())
+                .collect();
+            json!({ "name": name, "index": index, "routes": routes })
+        })
+        .collect()
+}
+
+async fn collect<C: ViciConnector>(connector: &C, config: &Config, interfaces: Value) -> Value {
+    json!({
+        "charon": charon_state(connector).await,
+        "configs": generated_configs(config),
+        "interfaces": interfaces,
+        "events": recent_events(config.daemon.event_log.as_ref().map(Path::new)),
+    })
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn _run(args: &DiagArgs, config: &Config) {
+    let interfaces = interfaces(&Netlink::new().await, config).await;
+    let bundle = collect(
+        &SocketConnector(config.vici_socket(), config.vici_timeouts()),
+        config,
+        interfaces,
+    )
+    .await;
+
+    fs::write(&args.output, serde_json::to_vec_pretty(&bundle).unwrap())
+        .expect("failed to write diagnostic bundle");
+    info!("diagnostic bundle was written to {:?}", args.output);
+}
+
+pub fn run(args: &DiagArgs, config: &Config) {
+    _run(args, config);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::vici::mock::{MockConnector, MockState};
+
+    #[tokio::test]
+    async fn test_collect() {
+        let mut config: Config = serde_json::from_str(
+            r#"{
+                "private_key": "",
+                "organization": "example",
+                "common_name": "local",
+                "daemon": { "network": "fd00::1/64", "config_gen_dir": "/nonexistent" },
+                "endpoints": []
+            }"#,
+        )
+        .unwrap();
+        let events = std::env::temp_dir().join(format!("aronet-diag-{}.jsonl", std::process::id()));
+        fs::write(&events, "{\"event\":\"down\"}\n{\"event\":\"up\"}\n").unwrap();
+        config.daemon.event_log = Some(events.display().to_string());
+
+        let connector = MockConnector::default();
+        connector.0.borrow_mut().sas = vec!["conn-a".to_string()];
+        let bundle = collect(&connector, &config, json!([])).await;
+        fs::remove_file(&events).unwrap();
+
+        for key in ["charon", "configs", "interfaces", "events"] {
+            assert!(bundle.get(key).is_some(), "missing section {key}");
+        }
+        assert_eq!(bundle["charon"]["version"]["daemon"], "charon");
+        assert_eq!(bundle["charon"]["stats"]["ikesas"]["total"], "1");
+        assert!(bundle["charon"]["sas"]["conn-a"].is_object());
+        assert!(bundle["charon"]["counters"].is_object());
+        assert!(bundle["configs"]["/nonexistent/bird.conf"]["error"].is_string());
+        assert_eq!(bundle["events"][0]["event"], "down");
+
+        // sections of charon are replaced with the error if it isn't reachable
+        let connector = MockConnector(std::rc::Rc::new(std::cell::RefCell::new(MockState {
+            unreachable: true,
+            ..Default::default()
+        })));
+        let bundle = collect(&connector, &config, json!([])).await;
+        assert!(bundle["charon"]["error"].is_string());
+    }
+}
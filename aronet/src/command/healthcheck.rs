@@ -0,0 +1,65 @@
+use clap::Args;
+
+use crate::utils::configuration::Config;
+use crate::utils::vici::{SocketConnector, ViciApi, ViciConnector};
+
+#[derive(Args, Debug)]
+pub struct HealthcheckArgs {
+    /// print the reason when charon isn't reachable
+    #[arg(short, long)]
+    verbose: bool,
+}
+
+/// Exit code of the probe, 0 if charon answers `version` on the vici socket.
+async fn check<C: ViciConnector>(connector: &C, verbose: bool) -> i32 {
+    let r = match connector.connect().await {
+        Ok(mut vici) => vici.version().await,
+        Err(e) => Err(e),
+    };
+
+    match r {
+        Ok(version) => {
+            if verbose {
+                println!("charon is reachable: {version:?}");
+            }
+            0
+        }
+        Err(e) => {
+            if verbose {
+                println!("charon isn't reachable: {e}");
+            }
+            1
+        }
+    }
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn _run(args: &HealthcheckArgs, config: &Config) -> i32 {
+    check(
+        &SocketConnector(config.vici_socket(), config.vici_timeouts()),
+        args.verbose,
+    )
+    .await
+}
+
+pub fn run(args: &HealthcheckArgs, config: &Config) {
+    std::process::exit(_run(args, config));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::vici::mock::{MockConnector, MockState};
+
+    #[tokio::test]
+    async fn test_check() {
+        let connector = MockConnector::default();
+        assert_eq!(check(&connector, false).await, 0);
+
+        let connector = MockConnector(std::rc::Rc::new(std::cell::RefCell::new(MockState {
+            unreachable: true,
+            ..Default::default()
+        })));
+        assert_eq!(check(&connector, false).await, 1);
+    }
+}
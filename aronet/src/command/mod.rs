@@ -1,12 +1,29 @@
+mod bench;
 mod birdcl;
+mod cleanup;
 mod daemon;
+mod decode_conn;
+mod diag;
+mod healthcheck;
+mod reconcile;
 mod swanctl;
+mod teardown;
+mod test_peer;
 
 use crate::utils::configuration::{Config, Registry};
+use bench::BenchArgs;
 use birdcl::BirdclArgs;
 use clap::{Parser, Subcommand};
+use cleanup::CleanupArgs;
 use daemon::DaemonArgs;
+use decode_conn::DecodeConnArgs;
+use diag::DiagArgs;
+use healthcheck::HealthcheckArgs;
+use log::warn;
+use reconcile::ReconcileArgs;
 use swanctl::SwanctlArgs;
+use teardown::TeardownArgs;
+use test_peer::TestPeerArgs;
 
 const CLI_ABOUT: &'static str = "aronet cli tool";
 const DEFAULT_CONFIG_PATH: &'static str = "/etc/aronet/config.json";
@@ -30,6 +47,24 @@ enum CommandType {
     Daemon(DaemonArgs),
     Swanctl(SwanctlArgs),
     Birdcl(BirdclArgs),
+    /// Load, initiate and unload connections to a single peer for troubleshooting
+    TestPeer(TestPeerArgs),
+    /// Terminate the SAs to a single peer and delete their interfaces, leaving other peers
+    /// untouched
+    Teardown(TeardownArgs),
+    /// Apply the differences between registry and running charon and routes, then exit
+    Reconcile(ReconcileArgs),
+    /// Exit with 0 if charon is reachable through the vici socket, 1 otherwise
+    Healthcheck(HealthcheckArgs),
+    /// Delete netns left over by aronet which no process is running in
+    Cleanup(CleanupArgs),
+    /// Write the state of charon, interfaces and routes into a JSON bundle for troubleshooting
+    Diag(DiagArgs),
+    /// Print the local and remote identities which a connection name refers to
+    DecodeConn(DecodeConnArgs),
+    /// Measure round-trip latencies of netlink and vici, to tell whether the kernel or charon is
+    /// slow
+    Bench(BenchArgs),
 }
 
 pub fn run() {
@@ -37,8 +72,11 @@ pub fn run() {
 
     match &cli.command {
         CommandType::Daemon(args) => {
-            let config = Config::parse(&cli.config).expect("cannot open configuration file");
+            let mut config = Config::parse(&cli.config).expect("cannot open configuration file");
             let registry = Registry::parse(&cli.registry).expect("cannot open registry file");
+            for mismatch in config.apply_registry_network(&registry) {
+                warn!("{mismatch}");
+            }
 
             daemon::run(args, &config, &registry);
         }
@@ -50,5 +88,43 @@ pub fn run() {
             let config = Config::parse(&cli.config).unwrap();
             birdcl::run(args, &config);
         }
+        CommandType::TestPeer(args) => {
+            let config = Config::parse(&cli.config).expect("cannot open configuration file");
+            let registry = Registry::parse(&cli.registry).expect("cannot open registry file");
+
+            test_peer::run(args, &config, &registry);
+        }
+        CommandType::Teardown(args) => {
+            let config = Config::parse(&cli.config).expect("cannot open configuration file");
+            let registry = Registry::parse(&cli.registry).expect("cannot open registry file");
+
+            teardown::run(args, &config, &registry);
+        }
+        CommandType::Reconcile(args) => {
+            let mut config = Config::parse(&cli.config).expect("cannot open configuration file");
+            let registry = Registry::parse(&cli.registry).expect("cannot open registry file");
+            for mismatch in config.apply_registry_network(&registry) {
+                warn!("{mismatch}");
+            }
+
+            reconcile::run(args, &config, &registry);
+        }
+        CommandType::Healthcheck(args) => {
+            let config = Config::parse(&cli.config).expect("cannot open configuration file");
+            healthcheck::run(args, &config);
+        }
+        CommandType::Cleanup(args) => {
+            let config = Config::parse(&cli.config).expect("cannot open configuration file");
+            cleanup::run(args, &config);
+        }
+        CommandType::Diag(args) => {
+            let config = Config::parse(&cli.config).expect("cannot open configuration file");
+            diag::run(args, &config);
+        }
+        CommandType::DecodeConn(args) => decode_conn::run(args),
+        CommandType::Bench(args) => {
+            let config = Config::parse(&cli.config).expect("cannot open configuration file");
+            bench::run(args, &config);
+        }
     }
 }
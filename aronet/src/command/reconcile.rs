@@ -0,0 +1,413 @@
+use clap::Args;
+use log::{info, warn};
+use netlink_packet_route::route::RouteProtocol;
+use tokio_util::sync::CancellationToken;
+
+use crate::daemon::bird::Bird;
+
+use crate::daemon::strongswan::{
+    ConnectionDiff, check_max_peers, derive_public_key, plan_connections,
+    plan_reconcile_connections, read_private_key, reconcile_connections,
+};
+use crate::utils::IpNetwork;
+use crate::utils::configuration::{Config, DaemonMode, Registries};
+use crate::utils::netlink::Netlink;
+use crate::utils::vici::Client;
+
+use super::daemon::{create_peer_route, create_static_route, main_static_routes, peer_routes};
+
+#[derive(Args, Debug)]
+pub struct ReconcileArgs {
+    /// print the changes without applying them
+    #[arg(long)]
+    plan: bool,
+}
+
+/// Routes changed by `reconcile_routes`.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct RouteDiff {
+    add: Vec<IpNetwork>,
+    delete: Vec<IpNetwork>,
+}
+
+fn diff_routes(desired: &[IpNetwork], actual: &[IpNetwork]) -> RouteDiff {
+    RouteDiff {
+        add: desired
+            .iter()
+            .filter(|net| !actual.contains(net))
+            .copied()
+            .collect(),
+        delete: actual
+            .iter()
+            .filter(|net| !desired.contains(net))
+            .copied()
+            .collect(),
+    }
+}
+
+/// whether routes of other nodes are deferred by the running daemon and not yet installed
+fn peer_routes_deferred(config: &Config) -> bool {
+    config.daemon.defer_peer_routes && !config.peer_routes_state_path().exists()
+}
+
+/// Routes the daemon installs through the main interface, of other nodes and `static_routes`.
+/// Routes of other nodes are left to the daemon while they are deferred.
+fn desired_routes(config: &Config, registries: &Registries, deferred: bool) -> Vec<IpNetwork> {
+    let mut routes = if deferred {
+        vec![]
+    } else {
+        peer_routes(config, registries)
+    };
+    routes.extend(main_static_routes(config).map(|r| r.destination));
+    routes
+}
+
+/// Differences between the desired routes and the routes in the main table, as created by the
+/// daemon.
+async fn plan_routes(nl: &Netlink, config: &Config, registries: &Registries) -> Option<RouteDiff> {
+    match nl.get_routes(config.ifname(), 254, None).await {
+        Ok(actual) => {
+            let desired = desired_routes(config, registries, peer_routes_deferred(config));
+            Some(diff_routes(&desired, &actual))
+        }
+        Err(e) => {
+            warn!("failed to get routes of {}: {e}", config.ifname());
+            None
+        }
+    }
+}
+
+/// Add missing routes of other nodes and delete the stale ones.
+async fn reconcile_routes(config: &Config, registries: &Registries) {
+    let nl = Netlink::new().await;
+    let Some(diff) = plan_routes(&nl, config, registries).await else {
+        return;
+    };
+
+    for net in diff.add {
+        info!("adding route {net}");
+        let r = match main_static_routes(config).find(|r| r.destination == net) {
+            Some(route) => create_static_route(&nl, config, route).await,
+            None => create_peer_route(&nl, config, net).await,
+        };
+        if let Err(e) = r {
+            warn!("failed to add route {net}: {e}");
+        }
+    }
+
+    for net in diff.delete {
+        info!("deleting route {net}");
+        if let Err(e) = nl.delete_route(net, config.ifname(), 254, None).await {
+            warn!("failed to delete route {net}: {e}");
+        }
+    }
+}
+
+/// A step of moving routes of bird from the route table in use to the configured one, ordered
+/// so that routes stay in at least one table looked up by the rules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MigrationStep {
+    /// copy routes exported by bird into the new table
+    CopyRoutes { from: u32, to: u32 },
+    /// let bird export routes into the new table
+    ReconfigureBird,
+    /// delete routes of bird left in the old table
+    FlushTable(u32),
+}
+
+/// Steps to switch from table `before` to the configured one, empty if it isn't changed.
+fn plan_table_migration(config: &Config, before: u32) -> Result<Vec<MigrationStep>, String> {
+    let after = config.route_table();
+    if before == after {
+        return Ok(vec![]);
+    }
+    if config.daemon.mode == DaemonMode::Vrf {
+        return Err(format!(
+            "table of vrf {} can't be changed from {before} to {after} without restarting the daemon",
+            config.ifname()
+        ));
+    }
+
+    // fwmark rules only exist in vrf mode, no rule looks up the table otherwise
+    Ok(vec![
+        MigrationStep::CopyRoutes {
+            from: before,
+            to: after,
+        },
+        MigrationStep::ReconfigureBird,
+        MigrationStep::FlushTable(before),
+    ])
+}
+
+/// route table recorded by the running daemon
+fn active_route_table(config: &Config) -> Option<u32> {
+    std::fs::read_to_string(config.route_table_state_path())
+        .ok()
+        .and_then(|table| table.trim().parse().ok())
+}
+
+/// Apply the steps in order, stop at the first failure so that the old table keeps working.
+async fn migrate_route_table(
+    nl: &Netlink,
+    config: &Config,
+    registries: &Registries,
+    steps: &[MigrationStep],
+) -> Result<(), String> {
+    let netns = config.bird_netns();
+    for step in steps {
+        info!("migrating route table: {step:?}");
+        match *step {
+            MigrationStep::CopyRoutes { from, to } => {
+                nl.copy_routes(from, to, RouteProtocol::Bird, netns.as_deref())
+                    .await
+                    .map_err(|e| format!("failed to copy routes: {e}"))?;
+            }
+            MigrationStep::ReconfigureBird => {
+                Bird::new(config, registries, CancellationToken::new())
+                    .reconfigure()
+                    .await
+                    .map_err(|e| format!("failed to reconfigure bird: {e}"))?;
+            }
+            MigrationStep::FlushTable(table) => {
+                nl.flush_routes(table, RouteProtocol::Bird, netns.as_deref())
+                    .await
+                    .map_err(|e| format!("failed to flush table {table}: {e}"))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Move routes into the configured table if it was changed since the daemon started.
+async fn reconcile_route_table(config: &Config, registries: &Registries) {
+    let Some(before) = active_route_table(config) else {
+        return;
+    };
+
+    let steps = match plan_table_migration(config, before) {
+        Ok(steps) => steps,
+        Err(e) => {
+            warn!("{e}");
+            return;
+        }
+    };
+    if steps.is_empty() {
+        return;
+    }
+
+    let nl = Netlink::new().await;
+    match migrate_route_table(&nl, config, registries, &steps).await {
+        Ok(_) => {
+            info!(
+                "route table was migrated from {before} to {}",
+                config.route_table()
+            );
+            let table = config.route_table().to_string();
+            if let Err(e) = std::fs::write(config.route_table_state_path(), table) {
+                warn!("failed to record route table in use: {e}");
+            }
+        }
+        Err(e) => warn!("{e}"),
+    }
+}
+
+/// changes in the form of `+ connection <name>` or `- route <network>`, one per line
+fn render_plan(connections: &ConnectionDiff, routes: Option<&RouteDiff>) -> String {
+    let mut lines = vec![];
+    lines.extend(connections.load.iter().map(|n| format!("+ connection {n}")));
+    lines.extend(
+        connections
+            .unload
+            .iter()
+            .map(|n| format!("- connection {n}")),
+    );
+    if let Some(routes) = routes {
+        lines.extend(routes.add.iter().map(|n| format!("+ route {n}")));
+        lines.extend(routes.delete.iter().map(|n| format!("- route {n}")));
+    }
+
+    if lines.is_empty() {
+        "no changes\n".to_string()
+    } else {
+        lines.join("\n") + "\n"
+    }
+}
+
+async fn plan(vici: &mut Client, config: &Config, registries: &Registries) {
+    let connections = match plan_reconcile_connections(vici, config, registries).await {
+        Ok(diff) => diff,
+        Err(e) => {
+            warn!("failed to get connections: {e}");
+            return;
+        }
+    };
+
+    let routes = if config.daemon.mode != DaemonMode::Dummy {
+        plan_routes(&Netlink::new().await, config, registries).await
+    } else {
+        None
+    };
+
+    print!("{}", render_plan(&connections, routes.as_ref()));
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn _run(args: &ReconcileArgs, config: &Config, registries: &Registries) {
+    let mut vici = Client::connect_socket(&config.vici_socket(), config.vici_timeouts())
+        .await
+        .expect("cannot connect to vici socket, is the daemon running?");
+    if args.plan {
+        plan(&mut vici, config, registries).await;
+        return;
+    }
+
+    let pubkey = derive_public_key(&read_private_key(&config.private_key));
+
+    match reconcile_connections(&mut vici, config, registries, &pubkey).await {
+        Ok(diff) => info!(
+            "connections reconciled, {} loaded and {} unloaded",
+            diff.load.len(),
+            diff.unload.len()
+        ),
+        Err(e) => warn!("failed to reconcile connections: {e}"),
+    }
+
+    if config.daemon.mode != DaemonMode::Dummy {
+        reconcile_routes(config, registries).await;
+    }
+    reconcile_route_table(config, registries).await;
+}
+
+pub fn run(args: &ReconcileArgs, config: &Config, registries: &Registries) {
+    if let Err(e) = check_max_peers(config, &plan_connections(config, registries)) {
+        panic!("{e}");
+    }
+    _run(args, config, registries);
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn nets(networks: &[&str]) -> Vec<IpNetwork> {
+        networks
+            .iter()
+            .map(|n| IpNetwork::from_str(n).unwrap())
+            .collect()
+    }
+
+    fn config(daemon: &str) -> Config {
+        serde_json::from_str(&format!(
+            r#"{{
+                "private_key": "",
+                "organization": "example",
+                "common_name": "local",
+                "daemon": {daemon},
+                "endpoints": []
+            }}"#
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_plan_table_migration() {
+        let c = config(r#"{ "network": "fd00::1/64", "mode": "netns", "route_table": 100 }"#);
+        assert_eq!(
+            plan_table_migration(&c, 254).unwrap(),
+            vec![
+                MigrationStep::CopyRoutes { from: 254, to: 100 },
+                MigrationStep::ReconfigureBird,
+                MigrationStep::FlushTable(254),
+            ]
+        );
+        assert!(plan_table_migration(&c, 100).unwrap().is_empty());
+
+        let c = config(r#"{ "network": "fd00::1/64", "route_table": 100 }"#);
+        assert!(plan_table_migration(&c, 128).is_err());
+    }
+
+    #[test]
+    fn test_static_routes_kept() {
+        let c = config(
+            r#"{
+                "network": "fd00::1/64",
+                "mode": "netns",
+                "ifname": "aronet",
+                "static_routes": [
+                    { "destination": "192.0.2.0/24", "via": "fd01::1" },
+                    { "destination": "fd10::/48", "dev": "eth1" }
+                ]
+            }"#,
+        );
+        let registries: Registries = serde_json::from_str("[]").unwrap();
+        let desired = desired_routes(&c, &registries, false);
+        assert_eq!(desired, nets(&["192.0.2.0/24"]));
+
+        let diff = diff_routes(&desired, &nets(&["192.0.2.0/24", "fd03::/64"]));
+        assert_eq!(diff.delete, nets(&["fd03::/64"]));
+        assert!(diff.add.is_empty());
+    }
+
+    #[test]
+    fn test_deferred_peer_routes() {
+        let c = config(
+            r#"{
+                "network": "fd00::1/64",
+                "mode": "netns",
+                "ifname": "aronet",
+                "defer_peer_routes": true,
+                "static_routes": [{ "destination": "192.0.2.0/24", "via": "fd01::1" }]
+            }"#,
+        );
+        let registries: Registries = serde_json::from_str(
+            r#"[{
+                "public_key": "",
+                "organization": "example",
+                "nodes": [{
+                    "common_name": "remote",
+                    "endpoints": [{ "address": "2.2.2.2", "port": 12025, "serial_number": 0 }],
+                    "remarks": { "network": "fd01::1/64" }
+                }]
+            }]"#,
+        )
+        .unwrap();
+
+        // only static routes are added until the daemon installs routes of other nodes
+        let diff = diff_routes(&desired_routes(&c, &registries, true), &[]);
+        assert_eq!(diff.add, nets(&["192.0.2.0/24"]));
+
+        let diff = diff_routes(&desired_routes(&c, &registries, false), &[]);
+        assert_eq!(diff.add, nets(&["fd01::1/64", "192.0.2.0/24"]));
+    }
+
+    #[test]
+    fn test_render_plan() {
+        let routes = diff_routes(
+            &nets(&["fd01::/64", "fd02::/64"]),
+            &nets(&["fd02::/64", "fd03::/64"]),
+        );
+        assert_eq!(
+            routes,
+            RouteDiff {
+                add: nets(&["fd01::/64"]),
+                delete: nets(&["fd03::/64"]),
+            }
+        );
+
+        let connections = ConnectionDiff {
+            load: vec!["new".to_string()],
+            unload: vec!["stale".to_string()],
+        };
+        assert_eq!(
+            render_plan(&connections, Some(&routes)),
+            "+ connection new\n- connection stale\n+ route fd01::/64\n- route fd03::/64\n"
+        );
+        assert_eq!(
+            render_plan(&ConnectionDiff::default(), None),
+            "no changes\n"
+        );
+    }
+}
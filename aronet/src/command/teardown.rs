@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+
+use clap::Args;
+use log::{info, warn};
+
+use crate::daemon::strongswan::{MONITOR_INTERVAL, plan_connections, xfrm_name};
+use crate::utils::configuration::{Config, Registries, node_id};
+use crate::utils::netlink::Netlink;
+use crate::utils::vici::{Client, IkeSa, ViciApi};
+
+/// how long charon waits for the peer to confirm the deletion of an IKE_SA
+const TERMINATE_TIMEOUT_MS: i32 = 5000;
+
+#[derive(Args, Debug)]
+pub struct TeardownArgs {
+    /// organization of the peer
+    organization: String,
+    /// common name of the peer
+    common_name: String,
+    /// also unload the connections. Without it, the running daemon initiates them again within
+    /// seconds, with it the connections stay down until the daemon is restarted or reconciled
+    #[arg(long)]
+    unload: bool,
+}
+
+/// An established connection to the peer and its xfrm interface.
+#[derive(Debug, PartialEq, Eq)]
+struct Target {
+    connection: String,
+    interface: Option<String>,
+}
+
+/// Established connections to a single node, other nodes are left untouched.
+fn select_targets(
+    config: &Config,
+    registries: &Registries,
+    sas: &HashMap<String, IkeSa>,
+    organization: &str,
+    common_name: &str,
+) -> Vec<Target> {
+    let id = node_id(organization, common_name);
+
+    plan_connections(config, registries)
+        .into_iter()
+        .filter(|conn| conn.remote_node == id)
+        .filter_map(|conn| {
+            let sa = sas.get(&conn.name)?;
+            Some(Target {
+                interface: u32::from_str_radix(&sa.if_id_in, 16)
+                    .ok()
+                    .map(|if_id| xfrm_name(config.ifname(), if_id)),
+                connection: conn.name,
+            })
+        })
+        .collect()
+}
+
+/// Terminate IKE_SAs of the targets, returns the number of terminated ones.
+async fn terminate<V: ViciApi>(vici: &mut V, targets: &[Target], unload: bool) -> usize {
+    let mut terminated = 0;
+    for target in targets {
+        match vici
+            .terminate(&target.connection, TERMINATE_TIMEOUT_MS)
+            .await
+        {
+            Ok(_) => terminated += 1,
+            Err(e) => warn!("failed to terminate {}: {e}", target.connection),
+        }
+
+        if unload && let Err(e) = vici.unload_conn(&target.connection).await {
+            warn!("failed to unload {}: {e}", target.connection);
+        }
+    }
+
+    terminated
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn _run(args: &TeardownArgs, config: &Config, registries: &Registries) {
+    let mut vici = Client::connect_socket(&config.vici_socket(), config.vici_timeouts())
+        .await
+        .expect("cannot connect to vici socket, is the daemon running?");
+    let sas = vici
+        .list_sas()
+        .await
+        .map_err(|e| e.to_string())
+        .expect("failed to list SAs");
+
+    let targets = select_targets(
+        config,
+        registries,
+        &sas,
+        &args.organization,
+        &args.common_name,
+    );
+    if targets.is_empty() {
+        warn!(
+            "no SA with {} is established",
+            node_id(&args.organization, &args.common_name)
+        );
+        return;
+    }
+
+    let terminated = terminate(&mut vici, &targets, args.unload).await;
+    info!("{terminated} of {} SAs were terminated", targets.len());
+    if !args.unload {
+        info!(
+            "the daemon initiates the connections again within {MONITOR_INTERVAL:?}, \
+             pass --unload to keep them down"
+        );
+    }
+
+    // the daemon deletes interfaces when SAs go down, unless they linger
+    let nl = Netlink::new().await;
+    let netns = config.xfrm_netns();
+    for name in targets.iter().filter_map(|t| t.interface.as_ref()) {
+        match nl.delete_link(name, netns.as_deref()).await {
+            Ok(_) => info!("deleted interface {name}"),
+            Err(e) if e.is_netlink_not_found() => {}
+            Err(e) => warn!("failed to delete interface {name}: {e}"),
+        }
+    }
+}
+
+pub fn run(args: &TeardownArgs, config: &Config, registries: &Registries) {
+    _run(args, config, registries);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::daemon::strongswan::test::{local_config, registries};
+    use crate::utils::vici::ViciConnector;
+    use crate::utils::vici::mock::{MockConnector, MockState};
+
+    fn sa(if_id: &str) -> IkeSa {
+        IkeSa {
+            if_id_in: if_id.to_string(),
+            if_id_out: if_id.to_string(),
+            local_id: String::new(),
+            remote_id: String::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_teardown_single_peer() {
+        let config =
+            local_config(r#"[{ "address": "1.1.1.1", "port": 12025, "serial_number": 0 }]"#);
+        let registries = registries(
+            r#"[
+                { "address": "2.2.2.2", "port": 12025, "serial_number": 0 },
+                { "address": "2.2.2.3", "port": 12025, "serial_number": 1 }
+            ]"#,
+        );
+        let remote = plan_connections(&config, &registries)[0].name.clone();
+        // SA of a connection to another peer, e.g. loaded from swanctl_dir
+        let other = "other".to_string();
+
+        // only the first endpoint of the peer is connected
+        let sas = HashMap::from([
+            (remote.clone(), sa("0000002a")),
+            (other.clone(), sa("0000002b")),
+        ]);
+        let targets = select_targets(&config, &registries, &sas, "example", "remote");
+        assert_eq!(
+            targets,
+            vec![Target {
+                connection: remote.clone(),
+                interface: Some("aronet-0000002a".to_string()),
+            }]
+        );
+        assert!(select_targets(&config, &registries, &sas, "example", "unknown").is_empty());
+
+        let connector = MockConnector(std::rc::Rc::new(std::cell::RefCell::new(MockState {
+            conns: vec![remote.clone(), other.clone()],
+            sas: vec![remote.clone(), other.clone()],
+            ..Default::default()
+        })));
+        let mut vici = connector.connect().await.unwrap();
+        assert_eq!(terminate(&mut vici, &targets, true).await, 1);
+
+        let state = connector.0.borrow();
+        assert_eq!(state.terminated, vec![remote]);
+        assert_eq!(state.sas, vec![other.clone()]);
+        assert_eq!(state.conns, vec![other]);
+    }
+}
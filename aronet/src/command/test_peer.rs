@@ -0,0 +1,334 @@
+use std::io;
+
+use clap::Args;
+use log::{info, warn};
+use tokio::time::{Duration, Instant, sleep};
+
+use crate::daemon::strongswan::{
+    PlannedConnection, connection_options, derive_public_key, plan_connections, read_private_key,
+};
+use crate::utils::configuration::{Config, Registries, node_id};
+use crate::utils::vici::{Client, ConnectionOptions, InitiateOptions, ViciApi};
+
+/// Connections of the test are loaded under this prefix, so that the connections of a running
+/// daemon are left untouched.
+const TEST_CONN_PREFIX: &str = "test-";
+
+#[derive(Args, Debug)]
+pub struct TestPeerArgs {
+    /// organization of the peer
+    organization: String,
+    /// common name of the peer
+    common_name: String,
+    /// seconds to wait for the IKE_SA to establish
+    #[arg(short, long, default_value_t = 30)]
+    timeout: u64,
+    /// let charon reject the initiation when too many IKE_SAs are half-open
+    #[arg(long)]
+    init_limits: bool,
+}
+
+/// connections to every endpoint of a single node in registries
+fn plan_peer<'a>(
+    config: &'a Config,
+    registries: &'a Registries,
+    organization: &str,
+    common_name: &str,
+) -> Vec<PlannedConnection<'a>> {
+    let id = node_id(organization, common_name);
+
+    plan_connections(config, registries)
+        .into_iter()
+        .filter(|conn| conn.remote_node == id)
+        .collect()
+}
+
+/// Poll `list-sas` until an IKE_SA of the connection shows up.
+async fn wait_for_sa<V: ViciApi>(
+    vici: &mut V,
+    name: &str,
+    timeout: Duration,
+    interval: Duration,
+) -> io::Result<()> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        match vici.list_sas().await {
+            Ok(sas) if sas.contains_key(name) => return Ok(()),
+            Ok(_) => {}
+            Err(e) => warn!("failed to request \"list-sas\": {e}"),
+        }
+
+        if Instant::now() >= deadline {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "IKE_SA was not established in time",
+            ));
+        }
+        sleep(interval).await;
+    }
+}
+
+/// Names of planned connections with an IKE_SA of the daemon. The test connections use the same
+/// identities, so they would replace these SAs.
+async fn held_by_daemon<V: ViciApi>(
+    vici: &mut V,
+    plan: &[PlannedConnection<'_>],
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let sas = vici.list_sas().await?;
+    Ok(plan
+        .iter()
+        .filter(|conn| sas.contains_key(&conn.name))
+        .map(|conn| conn.name.clone())
+        .collect())
+}
+
+/// Load, initiate, terminate and unload a single connection, returns the error reported by charon.
+async fn test_connection<V: ViciApi>(
+    vici: &mut V,
+    conn: &PlannedConnection<'_>,
+    pubkey: &str,
+    options: &ConnectionOptions,
+    timeout: Duration,
+    init_limits: bool,
+) -> io::Result<()> {
+    let name = format!("{TEST_CONN_PREFIX}{}", conn.name);
+    vici.load_conn(
+        &name,
+        conn.local.peer_config(pubkey),
+        conn.remote.peer_config(conn.remote_pubkey),
+        &conn.options(options),
+    )
+    .await?;
+
+    let initiate = InitiateOptions {
+        timeout: timeout.as_millis() as i32,
+        init_limits,
+    };
+    let r = match vici.initiate(&name, &initiate).await {
+        Ok(_) => wait_for_sa(vici, &name, timeout, Duration::from_secs(1)).await,
+        Err(e) => Err(e),
+    };
+
+    // unloading leaves the IKE_SA up, so it is terminated explicitly
+    if let Err(e) = vici.terminate(&name, initiate.timeout).await {
+        warn!("failed to terminate IKE_SA {name}: {e}");
+    }
+    if let Err(e) = vici.unload_conn(&name).await {
+        warn!("failed to unload connection {name}: {e}");
+    }
+
+    r
+}
+
+/// returns whether at least one endpoint of the peer was connected
+async fn test_peer<V: ViciApi>(
+    vici: &mut V,
+    config: &Config,
+    registries: &Registries,
+    args: &TestPeerArgs,
+) -> bool {
+    let plan = plan_peer(config, registries, &args.organization, &args.common_name);
+    if plan.is_empty() {
+        warn!(
+            "no connection to {} was planned, check the registry and exclude_peers",
+            node_id(&args.organization, &args.common_name)
+        );
+        return false;
+    }
+
+    match held_by_daemon(vici, &plan).await {
+        Ok(held) if held.is_empty() => {}
+        Ok(held) => {
+            warn!(
+                "the daemon has IKE_SAs to the peer: {}, tear them down first",
+                held.join(", ")
+            );
+            return false;
+        }
+        Err(e) => {
+            warn!("failed to request \"list-sas\": {e}");
+            return false;
+        }
+    }
+
+    let pubkey = derive_public_key(&read_private_key(&config.private_key));
+    let options = connection_options(config);
+
+    let mut connected = false;
+    for conn in &plan {
+        if let Some(reason) = conn.skip {
+            warn!(
+                "{} -> {}: skipped, {reason:?}",
+                conn.local.id, conn.remote.id
+            );
+            continue;
+        }
+
+        let timeout = Duration::from_secs(args.timeout);
+        match test_connection(vici, conn, &pubkey, &options, timeout, args.init_limits).await {
+            Ok(_) => {
+                info!("{} -> {}: established", conn.local.id, conn.remote.id);
+                connected = true;
+            }
+            Err(e) => warn!("{} -> {}: failed, {e}", conn.local.id, conn.remote.id),
+        }
+    }
+
+    connected
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn _run(args: &TestPeerArgs, config: &Config, registries: &Registries) -> bool {
+    let mut vici = Client::connect_socket(&config.vici_socket(), config.vici_timeouts())
+        .await
+        .expect("cannot connect to vici socket, is the daemon running?");
+
+    test_peer(&mut vici, config, registries, args).await
+}
+
+pub fn run(args: &TestPeerArgs, config: &Config, registries: &Registries) {
+    if !_run(args, config, registries) {
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::vici::ViciConnector;
+    use crate::utils::vici::mock::{MockConnector, MockState};
+
+    fn config() -> Config {
+        serde_json::from_str(
+            r#"{
+                "private_key": "",
+                "organization": "example",
+                "common_name": "local",
+                "daemon": { "network": "fd00::1/64" },
+                "endpoints": [{ "address": "1.1.1.1", "port": 12025, "serial_number": 0 }]
+            }"#,
+        )
+        .unwrap()
+    }
+
+    fn registries() -> Registries {
+        serde_json::from_str(
+            r#"[{
+                "public_key": "",
+                "organization": "example",
+                "nodes": [
+                    {
+                        "common_name": "remote",
+                        "endpoints": [
+                            { "address": "2.2.2.2", "port": 12025, "serial_number": 0 },
+                            { "address": "2.2.2.3", "port": 12025, "serial_number": 1 }
+                        ],
+                        "remarks": { "network": "fd01::1/64" }
+                    },
+                    {
+                        "common_name": "other",
+                        "endpoints": [{ "address": "3.3.3.3", "port": 12025, "serial_number": 0 }],
+                        "remarks": { "network": "fd02::1/64" }
+                    }
+                ]
+            }]"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_plan_peer() {
+        let config = config();
+        let registries = registries();
+
+        let plan = plan_peer(&config, &registries, "example", "remote");
+        let remotes: Vec<&str> = plan.iter().map(|c| c.remote.id.as_str()).collect();
+        assert_eq!(
+            remotes,
+            vec![
+                "O=example,CN=remote,serialNumber=0",
+                "O=example,CN=remote,serialNumber=1"
+            ]
+        );
+
+        assert!(plan_peer(&config, &registries, "example", "unknown").is_empty());
+    }
+
+    async fn run_test_connection(state: MockState) -> (io::Result<()>, MockConnector) {
+        let config = config();
+        let registries = registries();
+        let plan = plan_peer(&config, &registries, "example", "other");
+
+        let connector = MockConnector(std::rc::Rc::new(std::cell::RefCell::new(state)));
+        let mut vici = connector.connect().await.unwrap();
+        let r = test_connection(
+            &mut vici,
+            &plan[0],
+            "local-pubkey",
+            &ConnectionOptions::default(),
+            Duration::from_millis(50),
+            false,
+        )
+        .await;
+
+        (r, connector)
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_sa() {
+        let (r, connector) = run_test_connection(MockState::default()).await;
+        assert!(r.is_ok());
+
+        let state = connector.0.borrow();
+        assert_eq!(state.initiated.len(), 1);
+        assert!(state.initiated[0].starts_with(TEST_CONN_PREFIX));
+        // the test IKE_SA is terminated, not only its connection unloaded
+        assert_eq!(state.terminated, state.initiated);
+        // the test connection is unloaded afterwards
+        assert!(state.conns.is_empty());
+
+        let (r, _) = run_test_connection(MockState {
+            initiate_error: Some("establishing CHILD_SA 'default' failed".to_string()),
+            ..Default::default()
+        })
+        .await;
+        assert_eq!(
+            r.unwrap_err().to_string(),
+            "establishing CHILD_SA 'default' failed"
+        );
+
+        // initiation succeeded but the SA never shows up
+        let connector = MockConnector::default();
+        let mut vici = connector.connect().await.unwrap();
+        let r = wait_for_sa(
+            &mut vici,
+            "conn",
+            Duration::from_millis(20),
+            Duration::from_millis(5),
+        )
+        .await;
+        assert_eq!(r.unwrap_err().kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[tokio::test]
+    async fn test_refuse_held_peer() {
+        let config = config();
+        let registries = registries();
+        let plan = plan_peer(&config, &registries, "example", "other");
+        let args = TestPeerArgs {
+            organization: "example".to_string(),
+            common_name: "other".to_string(),
+            timeout: 1,
+            init_limits: false,
+        };
+
+        let connector = MockConnector::default();
+        connector.0.borrow_mut().sas.push(plan[0].name.clone());
+        let mut vici = connector.connect().await.unwrap();
+        assert!(!test_peer(&mut vici, &config, &registries, &args).await);
+
+        let state = connector.0.borrow();
+        assert!(state.initiated.is_empty());
+        assert_eq!(state.sas, vec![plan[0].name.clone()]);
+    }
+}
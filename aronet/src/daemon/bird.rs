@@ -1,14 +1,27 @@
 use adler2::Adler32;
-use std::{path::PathBuf, process::Stdio};
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    io,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    process::Stdio,
+};
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::UnixStream;
+use tokio::time::Duration;
 
 use log::info;
 use tokio::fs::OpenOptions;
 use tokio_util::sync::CancellationToken;
 
-use crate::utils::{IpNetwork, configuration::DaemonMode, netlink::Netlink};
+use crate::utils::{
+    IpNetwork,
+    configuration::{Config, DaemonMode, Registries, RouterIdSource, RoutingProtocol},
+    netlink::Netlink,
+};
 
-use super::Daemon;
+use super::{Daemon, backup_generated, capture_stderr, stderr_stdio};
 
 macro_rules! BIRD_CONF {
     () => {
@@ -23,18 +36,20 @@ protocol device {{
 
 protocol kernel {{
   kernel table {route_table};
+  {kernel_metric}
   learn off;
   ipv6 sadr {{
-    export where source = RTS_BABEL;
+    export where source = {route_source};
     import none;
   }};
 }}
 
 protocol kernel {{
   kernel table {route_table};
+  {kernel_metric}
   learn off;
   ipv4 {{
-    export where source = RTS_BABEL;
+    export where source = {route_source};
     import none;
   }};
 }}
@@ -48,7 +63,13 @@ protocol static {{
   ipv6 sadr;
   {ipv6_networks}
 }}
+{routing}"#
+    };
+}
 
+macro_rules! BABEL_CONF {
+    () => {
+        r#"
 protocol babel {{
   {vrf_statement};
   ipv6 sadr {{
@@ -58,39 +79,130 @@ protocol babel {{
   ipv4 {{
     export all;
     import all;
-  }};
-  interface "{prefix}-*" {{
+  }};{interfaces}
+}}
+"#
+    };
+}
+
+/// interfaces of babel, `rtt` is empty for ones whose cost is derived from the measured RTT
+macro_rules! BABEL_INTERFACE_CONF {
+    () => {
+        r#"
+  interface "{pattern}" {{
     type tunnel;
-    rxcost 32;
-    hello interval 20 s;
-    rtt cost 1024;
-    rtt max 1024 ms;
+    rxcost {rxcost};
+    hello interval 20 s;{rtt}
     rx buffer 2000;
     check link;
+  }};"#
+    };
+}
+
+macro_rules! BGP_CONF {
+    () => {
+        r#"
+template bgp aronet_peer {{
+  {vrf_statement};
+  local as {asn};
+  multihop;{rr_client}
+  ipv6 sadr {{
+    export all;
+    import all;{next_hop_self}
+  }};
+  ipv4 {{
+    extended next hop on;
+    export all;
+    import all;{next_hop_self}
   }};
 }}
 "#
     };
 }
 
+macro_rules! BGP_NEIGHBOR_CONF {
+    () => {
+        r#"
+protocol bgp {name} from aronet_peer {{
+  neighbor {address} as {asn};
+}}
+"#
+    };
+}
+
+/// rxcost of tunnels in babel, before any cost of RTT
+const BASE_RXCOST: u16 = 32;
+/// RTT below `RTT_MIN` adds nothing to the cost, and RTT from `RTT_MAX` adds `RTT_COST`, like
+/// `rtt cost` of babel
+const RTT_COST: u16 = 1024;
+const RTT_MIN: Duration = Duration::from_millis(10);
+const RTT_MAX: Duration = Duration::from_millis(1024);
+
+/// rxcost of an interface with the RTT, growing linearly between `RTT_MIN` and `RTT_MAX`
+pub fn rtt_to_cost(rtt: Duration) -> u16 {
+    let rtt = rtt.clamp(RTT_MIN, RTT_MAX);
+    let extra = (rtt - RTT_MIN).as_millis() * RTT_COST as u128 / (RTT_MAX - RTT_MIN).as_millis();
+
+    BASE_RXCOST + extra as u16
+}
+
+fn router_id(source: RouterIdSource, main_address: IpAddr, mac: &[u8]) -> u32 {
+    match source {
+        RouterIdSource::Mac => {
+            let mut adler = Adler32::new();
+            adler.write_slice(mac);
+            adler.checksum()
+        }
+        RouterIdSource::MainAddressV4 => match main_address {
+            IpAddr::V4(ip) => ip.to_bits(),
+            IpAddr::V6(ip) => ip.to_bits() as u32,
+        },
+        RouterIdSource::Explicit(id) => id.to_bits(),
+    }
+}
+
 pub struct Bird<'a> {
     conf_path: PathBuf,
     route_table: u32,
     ifname: &'a str,
     networks: Vec<IpNetwork>,
     bird_path: PathBuf,
+    socket_path: PathBuf,
+    keep_backup: bool,
     daemon_mode: DaemonMode,
-    netns: String,
+    netns: Option<String>,
+    capture_logs: bool,
+    kernel_metric: Option<u32>,
+    routing_protocol: RoutingProtocol,
+    router_id_source: RouterIdSource,
+    /// address of this node in `network`
+    main_address: IpAddr,
+    bgp_asn: u32,
+    /// name of protocol and overlay address of every peer, used in bgp mode. The address is
+    /// reached through a host route over the xfrm interface, created by strongswan on SA up
+    bgp_neighbors: Vec<(String, IpAddr)>,
+    relay: bool,
+    /// rxcost overriding the one of babel, keyed by interface name
+    interface_costs: RefCell<BTreeMap<String, u16>>,
     cancel_token: CancellationToken,
 }
 
 impl<'a> Bird<'a> {
-    pub fn new(config: &'a crate::utils::configuration::Config, token: CancellationToken) -> Self
+    pub fn new(config: &'a Config, registries: &Registries, token: CancellationToken) -> Self
     where
         Self: Sized,
     {
-        let mut networks = config.extra_network();
-        networks.push(config.daemon.network.clone());
+        let networks = config.advertised_networks();
+
+        let bgp_neighbors = config
+            .peers(registries)
+            .into_iter()
+            .map(|(registry, node)| {
+                let name = format!("{}_{}", registry.organization, node.common_name)
+                    .replace(|c: char| !c.is_ascii_alphanumeric(), "_");
+                (format!("peer_{name}"), node.remarks.main_network().ip)
+            })
+            .collect();
 
         Bird {
             conf_path: config.bird_conf_path(),
@@ -98,30 +210,24 @@ impl<'a> Bird<'a> {
             ifname: config.ifname(),
             networks,
             bird_path: config.bird_path(),
+            socket_path: config.bird_socket_path(),
+            keep_backup: config.daemon.keep_generated_backup,
             daemon_mode: config.daemon.mode,
-            netns: config.netns_name(),
+            netns: config.bird_netns(),
+            capture_logs: config.capture_subprocess_logs(),
+            kernel_metric: config.daemon.kernel_metric,
+            routing_protocol: config.daemon.routing_protocol,
+            router_id_source: config.daemon.router_id_source,
+            main_address: config.main_network().ip,
+            bgp_asn: config.bgp_asn(),
+            bgp_neighbors,
+            relay: config.daemon.relay,
+            interface_costs: RefCell::new(BTreeMap::new()),
             cancel_token: token,
         }
     }
 
-    async fn run_bird(&self) {
-        let nl = Netlink::new().await;
-        let link = nl.get_link(self.ifname, None).await.unwrap();
-        let mut router_id: u32 = 0;
-        for attr in link.attributes {
-            match attr {
-                netlink_packet_route::link::LinkAttribute::Address(addr) => {
-                    let mut adler = Adler32::new();
-                    adler.write_slice(&addr);
-                    router_id = adler.checksum();
-                    break;
-                }
-                _ => {
-                    continue;
-                }
-            }
-        }
-
+    fn render_config(&self, router_id: u32) -> String {
         let mut networks_v4 = String::new();
         let mut networks_v6 = String::new();
 
@@ -135,59 +241,174 @@ impl<'a> Bird<'a> {
             }
         }
 
-        info!("generating configuration of bird...");
-        let mut conf_file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(self.conf_path.as_path())
-            .await
-            .unwrap();
         let mut vrf_statement = "".to_string();
         if self.daemon_mode == DaemonMode::Vrf {
             vrf_statement = format!("vrf \"{}\"", self.ifname);
         }
 
-        let conf_str = format!(
+        let mut kernel_metric = "".to_string();
+        if let Some(metric) = self.kernel_metric {
+            kernel_metric = format!("metric {metric};");
+        }
+
+        // bird applies the first matching interface, so overrides go before the wildcard, which
+        // leaves the cost to babel's own RTT measurement
+        let mut interfaces: String = self
+            .interface_costs
+            .borrow()
+            .iter()
+            .map(|(name, rxcost)| {
+                format!(
+                    BABEL_INTERFACE_CONF!(),
+                    pattern = name,
+                    rxcost = rxcost,
+                    rtt = ""
+                )
+            })
+            .collect();
+        interfaces.push_str(&format!(
+            BABEL_INTERFACE_CONF!(),
+            pattern = format!("{}-*", self.ifname),
+            rxcost = BASE_RXCOST,
+            rtt = format!(
+                "\n    rtt cost {RTT_COST};\n    rtt max {} ms;",
+                RTT_MAX.as_millis()
+            )
+        ));
+
+        let (routing, route_source) = match self.routing_protocol {
+            RoutingProtocol::Babel => (
+                format!(
+                    BABEL_CONF!(),
+                    vrf_statement = vrf_statement,
+                    interfaces = interfaces
+                ),
+                "RTS_BABEL",
+            ),
+            RoutingProtocol::Bgp => {
+                // all peers are in the same AS, routes learned from a peer are only passed to
+                // others by a route reflector. Peers may not reach each other directly, so the
+                // relay also sets itself as next hop
+                let (rr_client, next_hop_self) = if self.relay {
+                    ("\n  rr client;", "\n    next hop self;")
+                } else {
+                    ("", "")
+                };
+                let mut bgp = format!(
+                    BGP_CONF!(),
+                    vrf_statement = vrf_statement,
+                    asn = self.bgp_asn,
+                    rr_client = rr_client,
+                    next_hop_self = next_hop_self
+                );
+                for (name, address) in &self.bgp_neighbors {
+                    bgp.push_str(&format!(
+                        BGP_NEIGHBOR_CONF!(),
+                        name = name,
+                        address = address,
+                        asn = self.bgp_asn
+                    ));
+                }
+                (bgp, "RTS_BGP")
+            }
+        };
+
+        format!(
             BIRD_CONF!(),
             route_table = self.route_table,
-            prefix = self.ifname,
+            route_source = route_source,
+            routing = routing,
+            kernel_metric = kernel_metric,
             ipv4_networks = networks_v4,
             ipv6_networks = networks_v6,
-            vrf_statement = vrf_statement,
             router_id = router_id
-        );
+        )
+    }
+
+    /// MAC address of the main interface
+    async fn mac_address(&self) -> Vec<u8> {
+        let nl = Netlink::new().await;
+        let link = nl.get_link(self.ifname, None).await.unwrap();
+        for attr in link.attributes {
+            match attr {
+                netlink_packet_route::link::LinkAttribute::Address(addr) => {
+                    return addr;
+                }
+                _ => {
+                    continue;
+                }
+            }
+        }
+
+        vec![]
+    }
+
+    /// generate bird.conf with the router id from `router_id_source`
+    pub async fn write_config(&self) {
+        let mac = match self.router_id_source {
+            RouterIdSource::Mac => self.mac_address().await,
+            _ => vec![],
+        };
+        let router_id = router_id(self.router_id_source, self.main_address, &mac);
+
+        info!("generating configuration of bird...");
+        if self.keep_backup {
+            backup_generated(&self.conf_path).await;
+        }
+        let mut conf_file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(self.conf_path.as_path())
+            .await
+            .unwrap();
+        let conf_str = self.render_config(router_id);
 
         conf_file.write_all(conf_str.as_bytes()).await.unwrap();
         conf_file.shutdown().await.unwrap();
+    }
+
+    /// Replace rxcost overrides of interfaces, applied by the next `reconfigure`.
+    pub fn set_interface_costs(&self, costs: BTreeMap<String, u16>) {
+        *self.interface_costs.borrow_mut() = costs;
+    }
+
+    /// Regenerate bird.conf and let the running bird apply it in place, so that routing
+    /// adjacencies survive.
+    pub async fn reconfigure(&self) -> io::Result<()> {
+        self.write_config().await;
+
+        configure(&self.socket_path).await
+    }
+
+    async fn run_bird(&self) {
+        self.write_config().await;
+        if let Err(e) = validate_config(&self.bird_path, &self.conf_path).await {
+            panic!("generated {:?} is rejected by bird: {e}", self.conf_path);
+        }
 
         let mut nl = Netlink::new().await;
-        if self.daemon_mode == DaemonMode::Netns {
-            nl.pushns(&self.netns).unwrap();
+        if let Some(netns) = &self.netns {
+            nl.pushns(netns).unwrap();
         }
         info!("starting bird...");
         let mut bird = tokio::process::Command::new(self.bird_path.as_path())
             .arg("-c")
             .arg(self.conf_path.as_path())
             .arg("-f")
-            .stderr(Stdio::piped())
+            .arg("-s")
+            .arg(self.socket_path.as_path())
+            .stderr(stderr_stdio(self.capture_logs))
             .stdout(Stdio::null())
             .spawn()
             .expect("cannot launch bird");
 
-        if self.daemon_mode == DaemonMode::Netns {
+        if self.netns.is_some() {
             nl.popns().unwrap();
         }
 
-        let stderr = bird.stderr.take().unwrap();
-        let mut stderr_reader = BufReader::new(stderr).lines();
-
         // catch stderr
-        tokio::spawn(async move {
-            while let Some(line) = stderr_reader.next_line().await.unwrap() {
-                info!("{line}");
-            }
-        });
+        capture_stderr(&mut bird, "");
 
         tokio::select! {
             _ = bird.wait() => {}
@@ -199,8 +420,346 @@ impl<'a> Bird<'a> {
     }
 }
 
+/// Let bird parse the config without starting, fails with what bird reports about it.
+async fn validate_config(bird: &Path, conf: &Path) -> io::Result<()> {
+    let output = tokio::process::Command::new(bird)
+        .arg("-p")
+        .arg("-c")
+        .arg(conf)
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "bird exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Send a command over the control socket of bird, returns the lines of its reply. Fails with
+/// the reply if bird reports an error.
+async fn bird_command(socket: &Path, command: &str) -> io::Result<Vec<String>> {
+    let stream = UnixStream::connect(socket).await?;
+    let (read, mut write) = stream.into_split();
+    let mut lines = BufReader::new(read).lines();
+
+    // bird greets with `0001 BIRD <version> ready.`
+    read_reply(&mut lines).await?;
+    write.write_all(format!("{command}\n").as_bytes()).await?;
+    read_reply(&mut lines).await
+}
+
+/// Lines of a reply, which ends at the first line of a code followed by a space. Codes from 8000
+/// are errors.
+async fn read_reply<R: AsyncBufReadExt + Unpin>(
+    lines: &mut tokio::io::Lines<R>,
+) -> io::Result<Vec<String>> {
+    let mut reply = vec![];
+    loop {
+        let Some(line) = lines.next_line().await? else {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "bird closed the control socket",
+            ));
+        };
+        let code = line.get(..4).and_then(|c| c.parse::<u32>().ok());
+        let last = code.is_some() && line.as_bytes().get(4) == Some(&b' ');
+        reply.push(line);
+
+        if last {
+            if code.unwrap() >= 8000 {
+                return Err(io::Error::other(reply.join("\n")));
+            }
+            return Ok(reply);
+        }
+    }
+}
+
+/// reload bird.conf in the running bird
+async fn configure(socket: &Path) -> io::Result<()> {
+    let reply = bird_command(socket, "configure").await?;
+    info!(
+        "bird: {}",
+        reply.last().map(|l| &l[5..]).unwrap_or_default()
+    );
+
+    Ok(())
+}
+
 impl<'a> Daemon for Bird<'a> {
     async fn runner(&self) {
         self.run_bird().await;
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use crate::utils::configuration::Config;
+
+    use super::*;
+
+    fn config(daemon: &str) -> Config {
+        serde_json::from_str(&format!(
+            r#"{{
+                "private_key": "",
+                "organization": "example",
+                "common_name": "local",
+                "daemon": {daemon},
+                "endpoints": []
+            }}"#
+        ))
+        .unwrap()
+    }
+
+    /// bird answering one command with `reply` on a socket in a temp dir
+    async fn stub_bird(
+        name: &str,
+        reply: &'static str,
+    ) -> (PathBuf, tokio::task::JoinHandle<String>) {
+        let path = std::env::temp_dir().join(format!("aronet-{name}-{}.ctl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = tokio::net::UnixListener::bind(&path).unwrap();
+
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let (read, mut write) = stream.split();
+            write.write_all(b"0001 BIRD 2.15 ready.\n").await.unwrap();
+            let mut command = String::new();
+            BufReader::new(read).read_line(&mut command).await.unwrap();
+            write.write_all(reply.as_bytes()).await.unwrap();
+            command
+        });
+
+        (path, server)
+    }
+
+    #[tokio::test]
+    async fn test_configure() {
+        let (path, server) = stub_bird(
+            "configure",
+            "0002-Reading configuration from /run/aronet/bird.conf\n0003 Reconfigured\n",
+        )
+        .await;
+        let r = configure(&path).await;
+        assert_eq!(server.await.unwrap(), "configure\n");
+        std::fs::remove_file(&path).unwrap();
+        assert!(r.is_ok(), "{}", r.unwrap_err());
+
+        let (path, server) = stub_bird(
+            "configure-error",
+            "0002-Reading configuration from /run/aronet/bird.conf\n8002 bird.conf:3:1 syntax error\n",
+        )
+        .await;
+        let r = configure(&path).await;
+        server.await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(r.unwrap_err().to_string().contains("syntax error"));
+    }
+
+    #[tokio::test]
+    async fn test_validate_config() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("aronet-bird-parse-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // stands in for bird, which rejects configs with a marker of a template bug
+        let bird = dir.join("bird");
+        std::fs::write(
+            &bird,
+            "#!/bin/sh\n[ \"$1 $2\" = \"-p -c\" ] || exit 2\n\
+             if grep -q '{{' \"$3\"; then echo \"$3:1:1 syntax error\" >&2; exit 1; fi\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&bird, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        // charon must not be run in place of bird
+        let c = config(&format!(
+            r#"{{ "network": "fd00::1/64", "charon_path": "/bin/false", "bird_path": {:?} }}"#,
+            bird
+        ));
+        let conf = dir.join("bird.conf");
+        let b = Bird::new(&c, &vec![], CancellationToken::new());
+        assert_eq!(b.bird_path, bird);
+        let rendered = b.render_config(1);
+        std::fs::write(&conf, &rendered).unwrap();
+        let valid = validate_config(&b.bird_path, &conf).await;
+
+        // e.g. braces which weren't unescaped by the template
+        std::fs::write(
+            &conf,
+            rendered.replace("protocol device {", "protocol device {{"),
+        )
+        .unwrap();
+        let broken = validate_config(&bird, &conf).await;
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(valid.is_ok(), "{}", valid.unwrap_err());
+        let e = broken.unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert!(e.to_string().ends_with("syntax error"), "{e}");
+    }
+
+    #[test]
+    fn test_kernel_metric() {
+        let c = config(r#"{ "network": "fd00::1/64" }"#);
+        let conf = Bird::new(&c, &vec![], CancellationToken::new()).render_config(1);
+        assert!(!conf.contains("metric"));
+
+        let c = config(r#"{ "network": "fd00::1/64", "kernel_metric": 32 }"#);
+        let conf = Bird::new(&c, &vec![], CancellationToken::new()).render_config(1);
+        let kernels: Vec<&str> = conf.split("protocol kernel").skip(1).collect();
+        assert_eq!(kernels.len(), 2);
+        for kernel in kernels {
+            let block = &kernel[..kernel.find("\n}").unwrap()];
+            assert!(block.contains("metric 32;"), "{block}");
+        }
+    }
+
+    #[test]
+    fn test_advertised_networks() {
+        let c = config(
+            r#"{
+                "network": "fd00::1/64",
+                "extra_network": [
+                    "10.0.1.0/24",
+                    { "network": "10.0.2.0/24", "advertise": false },
+                    { "network": "fd01::/64", "advertise": true }
+                ]
+            }"#,
+        );
+        let conf = Bird::new(&c, &vec![], CancellationToken::new()).render_config(1);
+        assert!(conf.contains("route fd00::/64 from ::/0 unreachable;"));
+        assert!(conf.contains("route 10.0.1.0/24 unreachable;"));
+        assert!(conf.contains("route fd01::/64 from ::/0 unreachable;"));
+        assert!(!conf.contains("10.0.2.0/24"), "{conf}");
+
+        // still a network of this node, e.g. for extra_ip
+        assert_eq!(c.extra_network().len(), 3);
+    }
+
+    #[test]
+    fn test_rtt_to_cost() {
+        assert_eq!(rtt_to_cost(Duration::ZERO), BASE_RXCOST);
+        assert_eq!(rtt_to_cost(Duration::from_millis(10)), BASE_RXCOST);
+        assert_eq!(rtt_to_cost(Duration::from_millis(517)), BASE_RXCOST + 512);
+        assert_eq!(
+            rtt_to_cost(Duration::from_millis(1024)),
+            BASE_RXCOST + RTT_COST
+        );
+        assert_eq!(rtt_to_cost(Duration::from_secs(5)), BASE_RXCOST + RTT_COST);
+        assert!(rtt_to_cost(Duration::from_millis(50)) < rtt_to_cost(Duration::from_millis(200)));
+
+        let c = config(r#"{ "network": "fd00::1/64" }"#);
+        let bird = Bird::new(&c, &vec![], CancellationToken::new());
+        bird.set_interface_costs(BTreeMap::from([("aronet-0000002a".to_string(), 544)]));
+        let conf = bird.render_config(1);
+        let specific = conf.find("interface \"aronet-0000002a\"").unwrap();
+        assert!(specific < conf.find("interface \"aronet-*\"").unwrap());
+        assert!(conf[specific..].contains("rxcost 544;"));
+    }
+
+    #[test]
+    fn test_bgp_neighbors() {
+        let registries: Registries = serde_json::from_str(
+            r#"[{
+                "public_key": "",
+                "organization": "example",
+                "nodes": [
+                    {
+                        "common_name": "local",
+                        "endpoints": [],
+                        "remarks": { "network": "fd00::/64" }
+                    },
+                    {
+                        "common_name": "remote-1",
+                        "endpoints": [],
+                        "remarks": { "network": "fd01::/64" }
+                    },
+                    {
+                        "common_name": "remote-2",
+                        "endpoints": [],
+                        "remarks": { "network": "10.0.2.0/24" }
+                    }
+                ]
+            }]"#,
+        )
+        .unwrap();
+
+        let c = config(r#"{ "network": "fd00::/64" }"#);
+        let conf = Bird::new(&c, &registries, CancellationToken::new()).render_config(1);
+        assert!(conf.contains("protocol babel"));
+        assert!(!conf.contains("protocol bgp"));
+        assert!(conf.contains("export where source = RTS_BABEL;"));
+
+        let c =
+            config(r#"{ "network": "fd00::/64", "routing_protocol": "bgp", "bgp_asn": 65000 }"#);
+        let conf = Bird::new(&c, &registries, CancellationToken::new()).render_config(1);
+        assert!(!conf.contains("protocol babel"));
+        assert!(conf.contains("export where source = RTS_BGP;"));
+        assert!(conf.contains("local as 65000;"));
+        assert_eq!(conf.matches("protocol bgp ").count(), 2);
+        assert!(conf.contains(
+            "protocol bgp peer_example_remote_1 from aronet_peer {\n  neighbor fd01::1 as 65000;"
+        ));
+        assert!(conf.contains(
+            "protocol bgp peer_example_remote_2 from aronet_peer {\n  neighbor 10.0.2.1 as 65000;"
+        ));
+    }
+
+    #[test]
+    fn test_router_id() {
+        let mac = [0x02, 0x00, 0x00, 0x00, 0x00, 0x01];
+        let mut adler = Adler32::new();
+        adler.write_slice(&mac);
+        let v4 = IpAddr::from_str("10.1.2.3").unwrap();
+        let v6 = IpAddr::from_str("fd00::a01:203").unwrap();
+
+        assert_eq!(router_id(RouterIdSource::Mac, v4, &mac), adler.checksum());
+        assert_eq!(
+            router_id(RouterIdSource::MainAddressV4, v4, &mac),
+            0x0a010203
+        );
+        assert_eq!(
+            router_id(RouterIdSource::MainAddressV4, v6, &mac),
+            0x0a010203
+        );
+
+        let c = config(
+            r#"{ "network": "fd00::/64", "router_id_source": { "explicit": "192.0.2.1" } }"#,
+        );
+        assert_eq!(router_id(c.daemon.router_id_source, v6, &mac), 0xc0000201);
+        let c = config(r#"{ "network": "fd00::/64", "router_id_source": "main_address_v4" }"#);
+        assert_eq!(c.daemon.router_id_source, RouterIdSource::MainAddressV4);
+    }
+
+    #[test]
+    fn test_bgp_relay() {
+        let registries: Registries = serde_json::from_str(
+            r#"[{
+                "public_key": "",
+                "organization": "example",
+                "nodes": []
+            }]"#,
+        )
+        .unwrap();
+
+        let c = config(r#"{ "network": "fd00::/64", "routing_protocol": "bgp" }"#);
+        let conf = Bird::new(&c, &registries, CancellationToken::new()).render_config(1);
+        assert!(!conf.contains("rr client;"));
+        assert!(!conf.contains("next hop self;"));
+
+        let c = config(r#"{ "network": "fd00::/64", "routing_protocol": "bgp", "relay": true }"#);
+        let conf = Bird::new(&c, &registries, CancellationToken::new()).render_config(1);
+        assert!(conf.contains("  multihop;\n  rr client;\n"));
+        assert_eq!(conf.matches("    next hop self;\n").count(), 2);
+    }
+}
@@ -0,0 +1,136 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::warn;
+use serde::Serialize;
+
+use crate::utils::vici::Updown;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventKind {
+    Load,
+    Initiate,
+    Up,
+    Down,
+    Teardown,
+}
+
+/// a single line of the event log
+#[derive(Debug, Serialize)]
+pub struct Event<'a> {
+    /// seconds since unix epoch
+    pub timestamp: u64,
+    pub event: EventKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connection: Option<&'a str>,
+    /// identity of the remote endpoint
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peer: Option<&'a str>,
+}
+
+impl<'a> Event<'a> {
+    pub fn new(event: EventKind, connection: Option<&'a str>, peer: Option<&'a str>) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        Event {
+            timestamp,
+            event,
+            connection,
+            peer,
+        }
+    }
+}
+
+/// up or down events of every IKE_SA in an `ike-updown` event
+pub fn updown_events(event: &Updown) -> Vec<Event<'_>> {
+    let kind = if event.up == Some(true) {
+        EventKind::Up
+    } else {
+        EventKind::Down
+    };
+
+    event
+        .ike_sas
+        .iter()
+        .map(|(name, sa)| Event::new(kind, Some(name), Some(&sa.remote_id)))
+        .collect()
+}
+
+/// Append-only JSONL log of connection state transitions, nothing is written if no path is
+/// configured.
+pub struct EventLog(Option<File>);
+
+impl EventLog {
+    pub fn open(path: Option<&Path>) -> io::Result<Self> {
+        let file = match path {
+            Some(path) => Some(OpenOptions::new().create(true).append(true).open(path)?),
+            None => None,
+        };
+
+        Ok(EventLog(file))
+    }
+
+    pub fn record(&self, event: &Event) {
+        let Some(mut file) = self.0.as_ref() else {
+            return;
+        };
+
+        let mut line = serde_json::to_vec(event).unwrap();
+        line.push(b'\n');
+        // a line is written at once, so lines of concurrent writers are not interleaved
+        if let Err(e) = file.write_all(&line) {
+            warn!("failed to write event log: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_updown_event_log() {
+        let event: Updown = serde_json::from_str(
+            r#"{
+                "up": true,
+                "conn-a": {
+                    "if-id-in": "00000001",
+                    "if-id-out": "00000001",
+                    "local-id": "O=example,CN=local,serialNumber=0",
+                    "remote-id": "O=example,CN=remote,serialNumber=0"
+                }
+            }"#,
+        )
+        .unwrap();
+
+        let path = std::env::temp_dir().join(format!("aronet-events-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let log = EventLog::open(Some(&path)).unwrap();
+        for e in updown_events(&event) {
+            log.record(&e);
+        }
+        log.record(&Event::new(EventKind::Teardown, None, None));
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<serde_json::Value> = content
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["event"], "up");
+        assert_eq!(lines[0]["connection"], "conn-a");
+        assert_eq!(lines[0]["peer"], "O=example,CN=remote,serialNumber=0");
+        assert!(lines[0]["timestamp"].as_u64().unwrap() > 0);
+        assert_eq!(lines[1]["event"], "teardown");
+        assert!(lines[1].get("peer").is_none());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+
+use log::warn;
+use tokio::time::{Duration, Instant};
+
+/// Collapses identical warnings, e.g. of a peer which stays down, so that each of them is
+/// logged once per window with the number of repeats suppressed meanwhile.
+#[derive(Debug)]
+pub struct LogDedup {
+    window: Duration,
+    /// start of the window and the suppressed repeats, keyed by message
+    seen: HashMap<String, (Instant, u32)>,
+}
+
+impl LogDedup {
+    pub fn new(window: Duration) -> Self {
+        LogDedup {
+            window,
+            seen: HashMap::new(),
+        }
+    }
+
+    /// Lines to log for the message. Repeats within the window are suppressed, and counted
+    /// in a summary once the window has passed.
+    fn check(&mut self, message: &str, now: Instant) -> Vec<String> {
+        let window = self.window;
+        let summary = |message: &str, n: u32| {
+            format!("{message} (repeated {n} times in the last {window:?})")
+        };
+
+        let mut lines = vec![];
+        let expired: Vec<String> = self
+            .seen
+            .iter()
+            .filter(|(_, (since, _))| now >= *since + window)
+            .map(|(message, _)| message.clone())
+            .collect();
+        let mut repeated = None;
+        for expired in expired {
+            let (_, suppressed) = self.seen.remove(&expired).unwrap();
+            if suppressed == 0 {
+                continue;
+            }
+            if expired == message {
+                repeated = Some(suppressed);
+            } else {
+                lines.push(summary(&expired, suppressed));
+            }
+        }
+
+        match self.seen.get_mut(message) {
+            Some((_, suppressed)) => *suppressed += 1,
+            None => {
+                self.seen.insert(message.to_string(), (now, 0));
+                lines.push(match repeated {
+                    Some(n) => summary(message, n),
+                    None => message.to_string(),
+                });
+            }
+        }
+
+        lines
+    }
+
+    pub fn warn(&mut self, message: &str) {
+        for line in self.check(message, Instant::now()) {
+            warn!("{line}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let mut dedup = LogDedup::new(Duration::from_secs(60));
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        let a = "connection a was failed to initiate: timeout";
+        let b = "connection b was failed to initiate: timeout";
+
+        assert_eq!(dedup.check(a, at(0)), vec![a]);
+        // repeats within the window are collapsed, other messages are not
+        for i in 1..=5 {
+            assert!(dedup.check(a, at(i * 10)).is_empty());
+        }
+        assert_eq!(dedup.check(b, at(10)), vec![b]);
+
+        assert_eq!(
+            dedup.check(a, at(60)),
+            vec![format!("{a} (repeated 5 times in the last 60s)")]
+        );
+        assert!(dedup.check(a, at(70)).is_empty());
+
+        // b wasn't repeated, so it is logged as is after the window
+        assert_eq!(dedup.check(b, at(75)), vec![b]);
+
+        // the summary of a is logged even if a isn't repeated anymore
+        assert_eq!(
+            dedup.check(b, at(120)),
+            vec![format!("{a} (repeated 1 times in the last 60s)")]
+        );
+    }
+}
@@ -1,6 +1,94 @@
+use std::io;
+use std::path::Path;
+use std::process::Stdio;
+
+use log::{info, warn};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Child;
+use tokio::task::JoinHandle;
+
 pub mod bird;
+pub mod event_log;
+pub mod log_dedup;
 pub mod strongswan;
 
 pub trait Daemon {
     fn runner(&self) -> impl Future<Output = ()>;
 }
+
+/// stderr of subprocess is piped for capturing, otherwise inherited from aronet
+fn stderr_stdio(capture: bool) -> Stdio {
+    if capture {
+        Stdio::piped()
+    } else {
+        Stdio::inherit()
+    }
+}
+
+/// Re-log stderr of subprocess with aronet's logger, no task is spawned if stderr isn't piped.
+fn capture_stderr(child: &mut Child, prefix: &'static str) -> Option<JoinHandle<()>> {
+    let stderr = child.stderr.take()?;
+    let mut stderr_reader = BufReader::new(stderr).lines();
+
+    Some(tokio::spawn(async move {
+        while let Some(line) = stderr_reader.next_line().await.unwrap() {
+            info!("{prefix}{line}");
+        }
+    }))
+}
+
+/// Copy a generated config to `<path>.bak` before it is rewritten, failures are only logged.
+async fn backup_generated(path: &Path) {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(".bak");
+
+    match tokio::fs::copy(path, &backup).await {
+        Ok(_) => {}
+        // nothing was generated yet
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => warn!("failed to back up {}: {e}", path.display()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_capture_stderr() {
+        let mut child = tokio::process::Command::new("true")
+            .stderr(stderr_stdio(false))
+            .spawn()
+            .unwrap();
+        assert!(capture_stderr(&mut child, "").is_none());
+        child.wait().await.unwrap();
+
+        let mut child = tokio::process::Command::new("true")
+            .stderr(stderr_stdio(true))
+            .spawn()
+            .unwrap();
+        let task = capture_stderr(&mut child, "");
+        assert!(task.is_some());
+        child.wait().await.unwrap();
+        task.unwrap().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_backup_generated() {
+        let dir = std::env::temp_dir().join(format!("aronet-backup-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("bird.conf");
+
+        // nothing to back up on the first start
+        backup_generated(&path).await;
+        assert!(!dir.join("bird.conf.bak").exists());
+
+        std::fs::write(&path, "previous").unwrap();
+        backup_generated(&path).await;
+        std::fs::write(&path, "current").unwrap();
+        let backup = std::fs::read_to_string(dir.join("bird.conf.bak"));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(backup.unwrap(), "previous");
+    }
+}
@@ -1,14 +1,18 @@
 use crate::utils::AddressFamily;
 use std::{
+    collections::BTreeMap,
     env::current_exe,
-    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    fmt::Display,
+    net::{IpAddr, Ipv4Addr},
     path::PathBuf,
     str::FromStr,
+    time::Duration,
 };
 
 use serde::{Deserialize, Serialize};
 
 use super::IpNetwork;
+use super::vici::{ViciSocket, ViciTimeouts};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Config {
@@ -21,17 +25,174 @@ pub struct Config {
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct DaemonConfig {
-    pub extra_network: Option<Vec<IpNetwork>>,
+    pub extra_network: Option<Vec<ExtraNetwork>>,
     pub network: IpNetwork,
     #[serde(default)]
     pub mode: DaemonMode,
     pub extra_ip: Option<Vec<IpNetwork>>,
+    /// address of this node in `network`, the first address of `network` if not set
+    pub main_address: Option<IpAddr>,
+    /// address of the peer end of the veth in `network`, the second address of `network` if
+    /// not set
+    pub peer_address: Option<IpAddr>,
     pub runtime_dir: Option<String>,
+    /// directory of the vici socket, `runtime_dir` if not set
+    pub socket_dir: Option<String>,
+    /// directory of generated strongswan.conf, bird.conf and swanctl, `runtime_dir` if not set
+    pub config_gen_dir: Option<String>,
     pub charon_path: Option<String>,
     pub bird_path: Option<String>,
+    pub birdcl_path: Option<String>,
     pub ifname: Option<String>,
     pub route_table: Option<u32>,
     pub netns_name: Option<String>,
+    /// resolv.conf bind-mounted to `/etc/netns/<netns_name>/resolv.conf` in netns mode, so that
+    /// `ip netns exec` doesn't use the resolvers of the host, which may be unreachable
+    pub netns_resolv_conf: Option<String>,
+    /// netns in which charon runs in netns mode, the root netns if not set. It is deleted on
+    /// exit if aronet created it
+    pub charon_netns: Option<String>,
+    /// netns in which bird runs in netns mode, must be `netns_name` if set. Other modes run
+    /// bird in the root netns
+    pub bird_netns: Option<String>,
+    #[serde(default)]
+    pub make_before_break: bool,
+    pub capture_subprocess_logs: Option<bool>,
+    /// derive if_id of connections from peers instead of letting charon assign them
+    #[serde(default)]
+    pub deterministic_if_id: bool,
+    /// metric of routes exported to kernel by bird
+    pub kernel_metric: Option<u32>,
+    /// maximum number of connections initiating at the same time
+    pub max_concurrent_initiates: Option<usize>,
+    /// milliseconds to wait for the result of initiating a connection, 30000 if not set
+    pub initiate_timeout: Option<u32>,
+    /// apply charon's limits of half-open IKE_SAs to initiations of the daemon
+    #[serde(default)]
+    pub initiate_init_limits: bool,
+    /// identities in the form of `O=...,CN=...` of nodes which won't be connected
+    #[serde(default)]
+    pub exclude_peers: Vec<String>,
+    /// log `ike-rekey` and `child-rekey` events of charon at debug level
+    #[serde(default)]
+    pub subscribe_rekey: bool,
+    #[serde(default)]
+    pub dpd_action: DpdAction,
+    #[serde(default)]
+    pub child_mode: ChildMode,
+    /// offloading of CHILD_SAs to the NIC, charon's default is used if not set
+    pub hw_offload: Option<HwOffload>,
+    /// IKE fragmentation, charon's default is used if not set
+    pub fragmentation: Option<Fragmentation>,
+    /// whether charon caches fetched CRLs on disk, charon's default is used if not set
+    pub cache_crls: Option<bool>,
+    /// revocation checking of peer certificates, charon's default is used if not set
+    pub revocation: Option<Revocation>,
+    /// txqueuelen of the main interface and xfrm interfaces, kernel default if not set
+    pub txqueuelen: Option<u32>,
+    /// timeout in seconds for declaring a peer dead, charon's default is used if not set
+    pub dpd_timeout: Option<u64>,
+    /// seconds between full reauthentications of IKE_SAs, which are only rekeyed if not set.
+    /// Enable `make_before_break` to keep traffic flowing while reauthenticating
+    pub reauth_time: Option<u64>,
+    /// udp port of the NAT-T socket of charon, connections are bound to it when set
+    pub charon_nat_port: Option<u16>,
+    /// packets with this fwmark are routed by the table of the vrf, only in vrf mode
+    pub fwmark: Option<u32>,
+    #[serde(default)]
+    pub routing_protocol: RoutingProtocol,
+    /// In babel mode, override rxcost of each xfrm interface by a coarse RTT estimated from the
+    /// time of the handshake of its IKE_SA, not of the datapath. Bird is reconfigured as they are
+    /// measured. Only SAs initiated by this node are measured
+    #[serde(default)]
+    pub rtt_cost: bool,
+    #[serde(default)]
+    pub router_id_source: RouterIdSource,
+    /// AS number of BGP sessions, all nodes are in the same AS
+    pub bgp_asn: Option<u32>,
+    /// virtual IPs requested by peers are assigned from this network
+    pub pool: Option<IpNetwork>,
+    /// keys under `private` and certificates under `x509` of this directory are loaded into
+    /// charon, `private_key` can be left empty if the first key is the key of this node
+    pub credentials_dir: Option<String>,
+    /// IKE version of connections, 1 for IKEv1 and 2 for IKEv2
+    pub ike_version: Option<u32>,
+    /// log level of each subsystem of charon, e.g. `{ "ike": 2 }`
+    #[serde(default)]
+    pub charon_log_levels: BTreeMap<String, i32>,
+    /// interval in seconds of NAT-T keepalive packets, charon's default is used if not set
+    pub nat_keepalive: Option<u32>,
+    /// let charon install routes of CHILD_SAs, which conflicts with routes managed by bird
+    #[serde(default)]
+    pub charon_install_routes: bool,
+    /// let charon install virtual IPs assigned by peers
+    #[serde(default)]
+    pub charon_install_virtual_ip: bool,
+    /// address and port of tcp socket of vici, replacing the unix socket. vici isn't
+    /// authenticated, so it should only be reachable by trusted hosts
+    pub vici_tcp_listen: Option<String>,
+    /// seconds to wait for connecting to vici, 5 if not set
+    pub vici_connect_timeout: Option<u64>,
+    /// seconds to wait for the response of a vici request, 30 if not set. Initiations wait
+    /// `initiate_timeout` on top of it, event subscriptions never time out
+    pub vici_request_timeout: Option<u64>,
+    /// seconds to keep the xfrm interface after its IKE_SA goes down, it is reused if the
+    /// IKE_SA comes up again in the meantime
+    pub xfrm_linger: Option<u64>,
+    /// With bgp, reflect routes between peers with this node as next hop, so that peers which
+    /// can't connect to each other, e.g. both behind NAT, reach each other through it.
+    /// Connections are planned the same. A relay needs bgp and a public endpoint
+    #[serde(default)]
+    pub relay: bool,
+    /// interfaces, netns, addresses and routes created on startup are written to this file as
+    /// JSON once setup finishes
+    pub setup_report: Option<String>,
+    /// generated strongswan.conf and bird.conf are copied to `.bak` files before being
+    /// rewritten on start, so that the ones of a crashed run can be inspected
+    #[serde(default)]
+    pub keep_generated_backup: bool,
+    /// routes installed by setup after the routes of other nodes, into the table of the vrf in
+    /// vrf mode and the main table otherwise
+    #[serde(default)]
+    pub static_routes: Vec<StaticRoute>,
+    /// connection state transitions are appended to this file as JSON lines
+    pub event_log: Option<String>,
+    /// Interoperate with IPsec gateways other than aronet: charon listens on the standard ports
+    /// 500 and 4500 unless `charon_nat_port` is set, and ESP is only encapsulated in UDP when
+    /// NAT is detected, unless an endpoint sets `encap`.
+    #[serde(default)]
+    pub compat: bool,
+    /// Connections are established by the first packet to the peer instead of on startup, by
+    /// trap policies of charon. Nodes in registry can override it with their `on_demand`
+    #[serde(default)]
+    pub on_demand: bool,
+    /// install routes to networks of other nodes once the first IKE_SA is up instead of on
+    /// startup, so that traffic isn't blackholed into the main interface until then
+    #[serde(default)]
+    pub defer_peer_routes: bool,
+    /// In netns mode, the ipv4 default route in netns is on-link instead of via the main
+    /// address, for setups where the root netns doesn't route ipv4 via ipv6 nexthops. It also
+    /// falls back to on-link if the main address is unreachable
+    #[serde(default)]
+    pub netns_v4_onlink: bool,
+    /// take `network` and `extra_network` from the entry of this node in registry, which peers
+    /// route to, instead of the ones in config
+    #[serde(default)]
+    pub network_from_registry: bool,
+    /// only create interfaces for SAs whose remote id is an endpoint of a node in registries
+    #[serde(default)]
+    pub strict_peer_ids: bool,
+    /// only respond to peers and never initiate connections, e.g. behind a firewall which
+    /// drops outgoing IKE packets
+    #[serde(default)]
+    pub passive: bool,
+    /// refuse to start if the entry of this node in registry differs from config, instead of
+    /// only warning about it
+    #[serde(default)]
+    pub strict_self_entry: bool,
+    /// refuse to start if connections to more peers than this are planned, a guard against
+    /// a misconfigured registry fanning out to every node of it
+    pub max_peers: Option<usize>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
@@ -39,6 +200,7 @@ pub struct DaemonConfig {
 pub enum DaemonMode {
     Netns,
     Vrf,
+    Dummy,
 }
 
 impl Default for DaemonMode {
@@ -47,32 +209,166 @@ impl Default for DaemonMode {
     }
 }
 
+/// route configured by the operator, e.g. to reach an external service through a specific peer
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct StaticRoute {
+    pub destination: IpNetwork,
+    pub via: Option<IpAddr>,
+    /// output interface, the main interface if not set
+    pub dev: Option<String>,
+}
+
+/// action of CHILD_SA when the peer is detected dead
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DpdAction {
+    Clear,
+    Trap,
+    #[default]
+    Restart,
+}
+
+/// IPsec mode of CHILD_SAs
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ChildMode {
+    #[default]
+    Tunnel,
+    /// only protects traffic between the endpoints of a connection
+    Transport,
+}
+
+/// fragmentation of IKE messages, which may exceed the path MTU with certificates
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Fragmentation {
+    /// fragment if the peer supports it
+    Yes,
+    /// only accept fragmented messages
+    Accept,
+    /// fragment the IKE_SA_INIT request before knowing whether the peer supports it
+    Force,
+    No,
+}
+
+/// policy of checking the revocation status of peer certificates
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Revocation {
+    /// fail if the status isn't known to be good
+    Strict,
+    /// fail only if a CRL or OCSP URI is available but the status can't be fetched
+    Ifuri,
+    /// only fail if the certificate is known to be revoked
+    Relaxed,
+}
+
+/// offloading of IPsec processing to the NIC
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum HwOffload {
+    No,
+    /// offload if the NIC supports it
+    Auto,
+    /// installing the CHILD_SA fails if the NIC doesn't support it
+    Yes,
+}
+
+/// routing protocol run by bird over the tunnels
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RoutingProtocol {
+    #[default]
+    Babel,
+    Bgp,
+}
+
+/// where the router id of bird comes from
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RouterIdSource {
+    /// checksum of the MAC address of the main interface
+    #[default]
+    Mac,
+    /// the main address of `network`, lower 32 bits of it for ipv6
+    MainAddressV4,
+    /// e.g. `{ "explicit": "10.0.0.1" }`
+    Explicit(Ipv4Addr),
+}
+
+/// a single address or a list of them, e.g. the A and AAAA address of a dual-stack endpoint
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum EndpointAddress {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// a network in `extra_network`, either the network alone or e.g.
+/// `{ "network": "10.0.0.0/24", "advertise": false }`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum ExtraNetwork {
+    Network(IpNetwork),
+    Options {
+        network: IpNetwork,
+        /// whether bird advertises it to the mesh, peers route it to this node either way.
+        /// `true` if not set
+        advertise: Option<bool>,
+    },
+}
+
+impl ExtraNetwork {
+    pub fn network(&self) -> IpNetwork {
+        match self {
+            ExtraNetwork::Network(network) => *network,
+            ExtraNetwork::Options { network, .. } => *network,
+        }
+    }
+
+    pub fn is_advertised(&self) -> bool {
+        match self {
+            ExtraNetwork::Network(_) => true,
+            ExtraNetwork::Options { advertise, .. } => advertise.unwrap_or(true),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct EndpointsConfig {
-    pub address: Option<String>,
+    pub address: Option<EndpointAddress>,
     pub port: u16,
     pub serial_number: u32,
     pub address_family: Option<AddressFamily>,
+    /// force (or disable) ESP-in-UDP, decided by whether the endpoints are public if not set
+    pub encap: Option<bool>,
+    /// the address may change, connections to this endpoint accept IKE from any address
+    #[serde(default)]
+    pub dynamic: bool,
+    /// preference of the path through this endpoint, connections are initiated in the order
+    /// of the sum of priorities of both endpoints, highest first
+    #[serde(default)]
+    pub priority: u32,
 }
 
 const DEFAULT_RUNTIME_DIR: &'static str = "/var/run/aronet";
+/// standard ports of IKE and NAT-T, used in compat mode
+const IKE_PORT: u16 = 500;
+const NAT_T_PORT: u16 = 4500;
 
 impl EndpointsConfig {
     pub fn is_address_valid(&self) -> bool {
-        !(self.address.is_none() && self.address_family.is_none())
+        !(self.get_address().is_empty() && self.address_family.is_none())
     }
 
-    pub fn address_family(&self) -> AddressFamily {
-        if let Some(i) = &self.address {
-            let ip_result = IpAddr::from_str(&i);
-
-            // address could be domain name
-            if let Ok(ip) = ip_result {
-                if ip.is_ipv4() {
-                    return AddressFamily::Ip4;
-                } else {
-                    return AddressFamily::Ip6;
-                }
+    /// family of an address of this endpoint
+    fn family_of(&self, address: &str) -> AddressFamily {
+        // address could be domain name
+        if let Ok(ip) = IpAddr::from_str(address) {
+            if ip.is_ipv4() {
+                return AddressFamily::Ip4;
+            } else {
+                return AddressFamily::Ip6;
             }
         }
         if let Some(i) = self.address_family {
@@ -83,27 +379,89 @@ impl EndpointsConfig {
         }
     }
 
+    /// families of the addresses in the order of them, a dual-stack endpoint has both
+    pub fn address_families(&self) -> Vec<AddressFamily> {
+        let addresses = self.get_address();
+        if addresses.is_empty() {
+            return vec![self.address_family.unwrap_or(AddressFamily::Ip4)];
+        }
+
+        let mut families = vec![];
+        for address in &addresses {
+            let family = self.family_of(address);
+            if !families.contains(&family) {
+                families.push(family);
+            }
+        }
+
+        families
+    }
+
+    pub fn address_family(&self) -> AddressFamily {
+        self.address_families()[0]
+    }
+
     pub fn get_address(&self) -> Vec<String> {
-        if self.address.is_some() {
-            vec![self.address.clone().unwrap()]
-        } else {
-            vec![]
+        match &self.address {
+            Some(EndpointAddress::One(address)) => vec![address.clone()],
+            Some(EndpointAddress::Many(addresses)) => addresses.clone(),
+            None => vec![],
         }
     }
 
+    /// addresses whose family is one of `families`
+    pub fn addresses_in(&self, families: &[AddressFamily]) -> Vec<String> {
+        self.get_address()
+            .into_iter()
+            .filter(|address| families.contains(&self.family_of(address)))
+            .collect()
+    }
+
+    /// `remote_addrs` of connections to this endpoint in `families`. Known addresses are still
+    /// initiated to, `%any` lets charon also accept the endpoint from another address.
+    pub fn remote_addrs_in(&self, families: &[AddressFamily]) -> Vec<String> {
+        let mut addrs = self.addresses_in(families);
+        if self.dynamic || addrs.is_empty() {
+            addrs.push("%any".to_string());
+        }
+
+        addrs
+    }
+
     pub fn is_address_public(&self) -> bool {
-        !self.address.is_none()
+        !self.get_address().is_empty()
     }
 }
 
 impl Config {
     pub fn parse(path: &str) -> Result<Config, std::io::Error> {
         let config_file = std::fs::File::open(path)?;
-        let config: Config = serde_json::from_reader(config_file)?;
+        let mut config: Config = serde_json::from_reader(config_file)?;
+        config
+            .normalize_identity()
+            .and_then(|_| config.validate_netns())
+            .and_then(|_| config.validate_network())
+            .and_then(|_| config.validate_relay())
+            .and_then(|_| config.validate_fwmark())
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
 
         Ok(config)
     }
 
+    /// Trim `organization` and `common_name`, and reject characters which would corrupt the
+    /// identity `O=...,CN=...` built from them.
+    pub fn normalize_identity(&mut self) -> Result<(), String> {
+        for (field, value) in [
+            ("organization", &mut self.organization),
+            ("common_name", &mut self.common_name),
+        ] {
+            *value = value.trim().to_string();
+            validate_dn_value(field, value)?;
+        }
+
+        Ok(())
+    }
+
     pub fn runtime_dir(&self) -> PathBuf {
         if let Some(p) = self.daemon.runtime_dir.as_ref() {
             PathBuf::from(p)
@@ -112,12 +470,45 @@ impl Config {
         }
     }
 
+    pub fn socket_dir(&self) -> PathBuf {
+        if let Some(p) = self.daemon.socket_dir.as_ref() {
+            PathBuf::from(p)
+        } else {
+            self.runtime_dir()
+        }
+    }
+
+    pub fn config_gen_dir(&self) -> PathBuf {
+        if let Some(p) = self.daemon.config_gen_dir.as_ref() {
+            PathBuf::from(p)
+        } else {
+            self.runtime_dir()
+        }
+    }
+
     pub fn charon_pidfile_path(&self) -> PathBuf {
         self.runtime_dir().join("charon.pid")
     }
 
+    /// route table in use by the running daemon, compared with `route_table` on reconcile
+    pub fn route_table_state_path(&self) -> PathBuf {
+        self.runtime_dir().join("route_table")
+    }
+
+    /// netns created by the running daemon besides `netns_name`, one per line, deleted on
+    /// cleanup
+    pub fn created_netns_state_path(&self) -> PathBuf {
+        self.runtime_dir().join("created_netns")
+    }
+
+    /// exists once the running daemon installed routes of other nodes deferred by
+    /// `defer_peer_routes`
+    pub fn peer_routes_state_path(&self) -> PathBuf {
+        self.runtime_dir().join("peer_routes")
+    }
+
     pub fn swanctl_conf_dir(&self) -> PathBuf {
-        self.runtime_dir().join("swanctl")
+        self.config_gen_dir().join("swanctl")
     }
 
     pub fn ifname(&self) -> &str {
@@ -129,7 +520,7 @@ impl Config {
     }
 
     pub fn bird_conf_path(&self) -> PathBuf {
-        self.runtime_dir().join("bird.conf")
+        self.config_gen_dir().join("bird.conf")
     }
 
     pub fn route_table(&self) -> u32 {
@@ -137,7 +528,7 @@ impl Config {
             table
         } else {
             match self.daemon.mode {
-                DaemonMode::Netns => 254,
+                DaemonMode::Netns | DaemonMode::Dummy => 254,
                 DaemonMode::Vrf => 128,
             }
         }
@@ -160,7 +551,7 @@ impl Config {
     }
 
     pub fn bird_path(&self) -> PathBuf {
-        if let Some(p) = self.daemon.charon_path.as_ref() {
+        if let Some(p) = self.daemon.bird_path.as_ref() {
             PathBuf::from(p)
         } else {
             current_exe()
@@ -176,7 +567,7 @@ impl Config {
     }
 
     pub fn birdcl_path(&self) -> PathBuf {
-        if let Some(p) = self.daemon.charon_path.as_ref() {
+        if let Some(p) = self.daemon.birdcl_path.as_ref() {
             PathBuf::from(p)
         } else {
             current_exe()
@@ -204,23 +595,62 @@ impl Config {
     }
 
     pub fn vici_socket_path(&self) -> PathBuf {
-        self.runtime_dir().join("charon.vici")
+        self.socket_dir().join("charon.vici")
+    }
+
+    /// control socket of bird, which `birdcl` and reloads connect to
+    pub fn bird_socket_path(&self) -> PathBuf {
+        self.socket_dir().join("bird.ctl")
+    }
+
+    /// vici listens on tcp instead of the unix socket if `vici_tcp_listen` is set
+    pub fn vici_socket(&self) -> ViciSocket {
+        match self.daemon.vici_tcp_listen.as_ref() {
+            Some(addr) => ViciSocket::Tcp(addr.clone()),
+            None => ViciSocket::Unix(self.vici_socket_path()),
+        }
+    }
+
+    pub fn vici_timeouts(&self) -> ViciTimeouts {
+        ViciTimeouts {
+            connect: Some(Duration::from_secs(
+                self.daemon.vici_connect_timeout.unwrap_or(5),
+            )),
+            request: Some(Duration::from_secs(
+                self.daemon.vici_request_timeout.unwrap_or(30),
+            )),
+        }
     }
 
     pub fn strongswan_config_path(&self) -> PathBuf {
-        self.runtime_dir().join("strongswan.conf")
+        self.config_gen_dir().join("strongswan.conf")
     }
 
     pub fn extra_network(&self) -> Vec<IpNetwork> {
         let r: Vec<IpNetwork> = vec![];
 
         if let Some(networks) = &self.daemon.extra_network {
-            networks.clone()
+            networks.iter().map(ExtraNetwork::network).collect()
         } else {
             r
         }
     }
 
+    /// `network` and the advertised ones of `extra_network`, which bird exports to the mesh
+    pub fn advertised_networks(&self) -> Vec<IpNetwork> {
+        let mut networks: Vec<IpNetwork> = self
+            .daemon
+            .extra_network
+            .iter()
+            .flatten()
+            .filter(|n| n.is_advertised())
+            .map(ExtraNetwork::network)
+            .collect();
+        networks.push(self.daemon.network);
+
+        networks
+    }
+
     pub fn netns_name(&self) -> String {
         if let Some(name) = self.daemon.netns_name.as_ref() {
             name.clone()
@@ -229,36 +659,331 @@ impl Config {
         }
     }
 
-    pub fn main_network(&self) -> IpNetwork {
-        let orig = (self.daemon.network.to_bits() & self.daemon.network.mask_bits()) + 1;
+    /// netns of charon, `None` for the root netns
+    pub fn charon_netns(&self) -> Option<String> {
+        self.daemon.charon_netns.clone()
+    }
 
-        if self.daemon.network.ip.is_ipv4() {
-            IpNetwork {
-                ip: IpAddr::V4(Ipv4Addr::from_bits(orig.try_into().unwrap())),
-                mask: self.daemon.network.mask,
-            }
+    /// netns of bird, xfrm interfaces are moved into it in netns mode
+    pub fn bird_netns(&self) -> Option<String> {
+        if let Some(name) = self.daemon.bird_netns.as_ref() {
+            Some(name.clone())
+        } else if self.daemon.mode == DaemonMode::Netns {
+            Some(self.netns_name())
         } else {
-            IpNetwork {
-                ip: IpAddr::V6(Ipv6Addr::from_bits(orig.try_into().unwrap())),
-                mask: self.daemon.network.mask,
+            None
+        }
+    }
+
+    /// Reject netns of processes the mode can't work with. Only netns mode moves xfrm interfaces
+    /// from charon to bird, and bird must run in `netns_name` which routes of the veth are set
+    /// up in.
+    pub fn validate_netns(&self) -> Result<(), String> {
+        let mode = self.daemon.mode;
+        if self.daemon.charon_netns.is_some() && mode != DaemonMode::Netns {
+            return Err("charon_netns is only supported in netns mode".to_string());
+        }
+        if let Some(netns) = &self.daemon.bird_netns {
+            if mode != DaemonMode::Netns {
+                return Err("bird_netns is only supported in netns mode".to_string());
+            }
+            if *netns != self.netns_name() {
+                return Err(format!(
+                    "bird_netns {netns} differs from netns_name {}",
+                    self.netns_name()
+                ));
             }
         }
+
+        Ok(())
     }
 
-    pub fn peer_network(&self) -> IpNetwork {
-        let orig = (self.daemon.network.to_bits() & self.daemon.network.mask_bits()) + 2;
+    /// netns which xfrm interfaces of SAs end up in
+    pub fn xfrm_netns(&self) -> Option<String> {
+        match self.daemon.mode {
+            DaemonMode::Netns => self.bird_netns(),
+            _ => self.charon_netns(),
+        }
+    }
 
-        if self.daemon.network.ip.is_ipv4() {
-            IpNetwork {
-                ip: IpAddr::V4(Ipv4Addr::from_bits(orig.try_into().unwrap())),
-                mask: self.daemon.network.mask,
+    /// whether stderr of charon and bird is re-logged by aronet
+    pub fn capture_subprocess_logs(&self) -> bool {
+        self.daemon.capture_subprocess_logs.unwrap_or(true)
+    }
+
+    /// entry of this node in registries
+    pub fn own_node<'a>(&self, registries: &'a Registries) -> Option<&'a NodeConfig> {
+        registries
+            .iter()
+            .filter(|registry| registry.organization == self.organization)
+            .flat_map(|registry| &registry.nodes)
+            .find(|node| node.common_name == self.common_name)
+    }
+
+    /// Every `extra_ip` needs a network of its family in `network` or `extra_network`, and
+    /// must not be the address of either end of the main interface.
+    pub fn validate_extra_ip(&self) -> Result<(), String> {
+        let networks: Vec<IpNetwork> = std::iter::once(self.daemon.network)
+            .chain(self.extra_network())
+            .collect();
+        let mut reserved = vec![("main address", self.main_network().ip)];
+        // the peer end only exists in netns mode
+        if self.daemon.mode == DaemonMode::Netns {
+            reserved.push(("peer address", self.peer_network().ip));
+        }
+
+        for extra in self.daemon.extra_ip.iter().flatten() {
+            if !networks
+                .iter()
+                .any(|n| n.ip.is_ipv4() == extra.ip.is_ipv4())
+            {
+                return Err(format!(
+                    "extra_ip {} has no network of its family in network or extra_network",
+                    extra.ip
+                ));
             }
-        } else {
-            IpNetwork {
-                ip: IpAddr::V6(Ipv6Addr::from_bits(orig.try_into().unwrap())),
-                mask: self.daemon.network.mask,
+            if let Some((name, _)) = reserved.iter().find(|(_, ip)| *ip == extra.ip) {
+                return Err(format!("extra_ip {} collides with the {name}", extra.ip));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Descriptions of networks in config which differ from the ones in `remarks`.
+    pub fn network_mismatches(&self, remarks: &Remarks) -> Vec<String> {
+        let mut mismatches = vec![];
+        if self.daemon.network != remarks.network {
+            mismatches.push(format!(
+                "network is {} in config but {} in registry",
+                self.daemon.network, remarks.network
+            ));
+        }
+
+        let mut local = self.extra_network();
+        let mut remote = remarks.extra_network();
+        local.sort();
+        remote.sort();
+        if local != remote {
+            mismatches.push(format!(
+                "extra_network is {local:?} in config but {remote:?} in registry"
+            ));
+        }
+
+        mismatches
+    }
+
+    /// Descriptions of endpoints in config which differ from the ones in `node`, endpoints are
+    /// paired by `serial_number`.
+    pub fn endpoint_mismatches(&self, node: &NodeConfig) -> Vec<String> {
+        let mut mismatches = vec![];
+        for local in &self.endpoints {
+            let Some(remote) = node
+                .endpoints
+                .iter()
+                .find(|e| e.serial_number == local.serial_number)
+            else {
+                mismatches.push(format!(
+                    "endpoint {} is in config but not in registry",
+                    local.serial_number
+                ));
+                continue;
+            };
+
+            let mut local_addrs = local.get_address();
+            let mut remote_addrs = remote.get_address();
+            local_addrs.sort();
+            remote_addrs.sort();
+            if local_addrs != remote_addrs {
+                mismatches.push(format!(
+                    "address of endpoint {} is {local_addrs:?} in config but {remote_addrs:?} in registry",
+                    local.serial_number
+                ));
+            }
+            if local.port != remote.port {
+                mismatches.push(format!(
+                    "port of endpoint {} is {} in config but {} in registry",
+                    local.serial_number, local.port, remote.port
+                ));
             }
         }
+
+        for remote in &node.endpoints {
+            if !self
+                .endpoints
+                .iter()
+                .any(|e| e.serial_number == remote.serial_number)
+            {
+                mismatches.push(format!(
+                    "endpoint {} is in registry but not in config",
+                    remote.serial_number
+                ));
+            }
+        }
+
+        mismatches
+    }
+
+    /// Differences between config and the entry of this node in registries, which peers
+    /// connect and route to. Nothing is compared if this node isn't in registries.
+    pub fn self_entry_mismatches(&self, registries: &Registries) -> Vec<String> {
+        let Some(node) = self.own_node(registries) else {
+            return vec![];
+        };
+
+        let mut mismatches = self.network_mismatches(&node.remarks);
+        mismatches.extend(self.endpoint_mismatches(node));
+
+        mismatches
+    }
+
+    /// Replace networks with the ones in the registry entry of this node if
+    /// `network_from_registry` is set, returns the differences which were overridden.
+    pub fn apply_registry_network(&mut self, registries: &Registries) -> Vec<String> {
+        if !self.daemon.network_from_registry {
+            return vec![];
+        }
+        let Some(node) = self.own_node(registries) else {
+            return vec![format!(
+                "{} isn't in registry, networks in config are used",
+                node_id(&self.organization, &self.common_name)
+            )];
+        };
+
+        let mismatches = self.network_mismatches(&node.remarks);
+        self.daemon.network = node.remarks.network;
+        self.daemon.extra_network = Some(node.remarks.extra_network.clone());
+
+        mismatches
+    }
+
+    pub fn is_peer_excluded(&self, organization: &str, common_name: &str) -> bool {
+        let id = node_id(organization, common_name);
+        self.daemon.exclude_peers.contains(&id)
+    }
+
+    pub fn max_concurrent_initiates(&self) -> usize {
+        self.daemon.max_concurrent_initiates.unwrap_or(16)
+    }
+
+    /// IKE port of charon, only the NAT-T port is used unless in compat mode
+    pub fn charon_port(&self) -> u16 {
+        if self.daemon.compat { IKE_PORT } else { 0 }
+    }
+
+    pub fn charon_nat_port(&self) -> u16 {
+        match self.daemon.charon_nat_port {
+            Some(port) => port,
+            None if self.daemon.compat => NAT_T_PORT,
+            None => 12025,
+        }
+    }
+
+    pub fn bgp_asn(&self) -> u32 {
+        self.daemon.bgp_asn.unwrap_or(4200000000)
+    }
+
+    /// Nodes in registries other than this node, the excluded and the disabled ones.
+    pub fn peers<'a>(&self, registries: &'a Registries) -> Vec<(&'a Registry, &'a NodeConfig)> {
+        let mut peers = vec![];
+
+        for registry in registries {
+            for node in &registry.nodes {
+                if (registry.organization == self.organization
+                    && node.common_name == self.common_name)
+                    || node.disabled
+                    || self.is_peer_excluded(&registry.organization, &node.common_name)
+                {
+                    continue;
+                }
+
+                peers.push((registry, node));
+            }
+        }
+
+        peers
+    }
+
+    /// whether connections to the node are established on demand
+    pub fn is_on_demand(&self, node: &NodeConfig) -> bool {
+        node.on_demand.unwrap_or(self.daemon.on_demand)
+    }
+
+    pub fn main_network(&self) -> IpNetwork {
+        address_in(&self.daemon.network, self.daemon.main_address, 1)
+    }
+
+    /// The fwmark rule is installed in the root netns, only the table of the vrf has routes of
+    /// aronet there. Netns and dummy mode route through the main table, which needs no rule.
+    pub fn validate_fwmark(&self) -> Result<(), String> {
+        if self.daemon.fwmark.is_some() && self.daemon.mode != DaemonMode::Vrf {
+            return Err("fwmark is only supported in vrf mode".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Routes are only relayed by bgp, and peers behind NAT can only connect to a relay with a
+    /// public endpoint.
+    pub fn validate_relay(&self) -> Result<(), String> {
+        if self.daemon.relay && self.daemon.routing_protocol != RoutingProtocol::Bgp {
+            return Err("relay is only supported with bgp".to_string());
+        }
+        if self.daemon.relay && !self.endpoints.iter().any(|e| e.is_address_public()) {
+            return Err("relay needs an endpoint with a public address".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Check `network` has room for the addresses derived from it.
+    pub fn validate_network(&self) -> Result<(), String> {
+        let network = &self.daemon.network;
+        validate_address_in("main address", network, self.daemon.main_address, 1)?;
+        // the peer end only exists in netns mode
+        if self.daemon.mode == DaemonMode::Netns {
+            validate_address_in("peer address", network, self.daemon.peer_address, 2)?;
+
+            let main = self.main_network().ip;
+            if main == self.peer_network().ip {
+                return Err(format!("main address and peer address are both {main}"));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn peer_network(&self) -> IpNetwork {
+        address_in(&self.daemon.network, self.daemon.peer_address, 2)
+    }
+}
+
+/// `address` with the mask of network, or the `n`th address of network if not set. Networks too
+/// small for it are rejected by `validate_address_in` on parse, network itself is returned then.
+fn address_in(network: &IpNetwork, address: Option<IpAddr>, n: u128) -> IpNetwork {
+    match address {
+        Some(ip) => IpNetwork {
+            ip,
+            mask: network.mask,
+        },
+        None => network.nth(n).unwrap_or(*network),
+    }
+}
+
+fn validate_address_in(
+    name: &str,
+    network: &IpNetwork,
+    address: Option<IpAddr>,
+    n: u128,
+) -> Result<(), String> {
+    match address {
+        Some(ip) if !network.contains(ip) => {
+            Err(format!("{name} {ip} is not in network {network}"))
+        }
+        None if network.nth(n).is_none() => Err(format!(
+            "network {network} is too small for the {name}, set it explicitly"
+        )),
+        _ => Ok(()),
     }
 }
 
@@ -275,7 +1000,28 @@ pub struct Registry {
 pub struct Remarks {
     pub network: IpNetwork,
     #[serde(default = "Vec::new")]
-    pub extra_network: Vec<IpNetwork>,
+    pub extra_network: Vec<ExtraNetwork>,
+    /// `main_address` of the node if it is pinned
+    pub main_address: Option<IpAddr>,
+}
+
+impl Remarks {
+    /// networks of `extra_network`, peers route them to the node whether advertised or not
+    pub fn extra_network(&self) -> Vec<IpNetwork> {
+        self.extra_network
+            .iter()
+            .map(ExtraNetwork::network)
+            .collect()
+    }
+
+    /// address of the node on its main interface, same as `Config::main_network` of that node
+    pub fn main_network(&self) -> IpNetwork {
+        address_in(&self.network, self.main_address, 1)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        validate_address_in("main address", &self.network, self.main_address, 1)
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -283,6 +1029,11 @@ pub struct NodeConfig {
     pub common_name: String,
     pub endpoints: Vec<EndpointsConfig>,
     pub remarks: Remarks,
+    /// other nodes don't connect or route to this node, while it stays in registry
+    #[serde(default)]
+    pub disabled: bool,
+    /// overrides `on_demand` of other nodes for connections to this node
+    pub on_demand: Option<bool>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -292,14 +1043,603 @@ impl Registry {
     pub fn parse(path: &str) -> Result<Registries, std::io::Error> {
         let registry_file = std::fs::File::open(path)?;
         let registry: Vec<Registry> = serde_json::from_reader(registry_file)?;
+        for node in registry.iter().flat_map(|r| &r.nodes) {
+            node.remarks.validate().map_err(|e| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("remarks of {}: {e}", node.common_name),
+                )
+            })?;
+        }
 
         Ok(registry)
     }
 }
 
+/// characters with special meaning in a DN, they are rejected instead of escaped so that
+/// identities stay the same in every registry
+const DN_SPECIAL_CHARS: [char; 8] = [',', '=', '+', '"', '\\', '<', '>', ';'];
+
+fn validate_dn_value(field: &str, value: &str) -> Result<(), String> {
+    if value.is_empty() {
+        return Err(format!("{field} is empty"));
+    }
+    if let Some(c) = value
+        .chars()
+        .find(|c| DN_SPECIAL_CHARS.contains(c) || c.is_control())
+    {
+        return Err(format!(
+            "{field} {value:?} contains invalid character {c:?}"
+        ));
+    }
+
+    Ok(())
+}
+
+/// identity of a node, without the serial number of endpoint
+pub fn node_id(organization: &str, common_name: &str) -> String {
+    format!("O={organization},CN={common_name}")
+}
+
 pub fn build_id(organization: &str, common_name: &str, endpoint: &EndpointsConfig) -> String {
-    format!(
-        "O={organization},CN={common_name},serialNumber={}",
-        endpoint.serial_number
-    )
+    Identity {
+        organization: organization.to_string(),
+        common_name: common_name.to_string(),
+        serial_number: endpoint.serial_number,
+    }
+    .to_string()
+}
+
+/// Identity of an endpoint in the form of `O=...,CN=...,serialNumber=...`, as built by
+/// `build_id`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identity {
+    pub organization: String,
+    pub common_name: String,
+    pub serial_number: u32,
+}
+
+impl Display for Identity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{},serialNumber={}",
+            node_id(&self.organization, &self.common_name),
+            self.serial_number
+        )
+    }
+}
+
+impl FromStr for Identity {
+    type Err = std::io::Error;
+
+    /// Values aren't escaped by `build_id`, so the fields are split at the last separators,
+    /// an organization containing `,` or `=` is still parsed correctly.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("invalid identity {s}"),
+            )
+        };
+
+        let rest = s.strip_prefix("O=").ok_or_else(invalid)?;
+        let (rest, serial_number) = rest.rsplit_once(",serialNumber=").ok_or_else(invalid)?;
+        let (organization, common_name) = rest.rsplit_once(",CN=").ok_or_else(invalid)?;
+
+        Ok(Identity {
+            organization: organization.to_string(),
+            common_name: common_name.to_string(),
+            serial_number: serial_number.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config(daemon: &str) -> Config {
+        serde_json::from_str(&format!(
+            r#"{{
+                "private_key": "",
+                "organization": "example",
+                "common_name": "local",
+                "daemon": {daemon},
+                "endpoints": []
+            }}"#
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_normalize_identity() {
+        let mut c = config(r#"{ "network": "fd00::1/64" }"#);
+        c.organization = " example ".to_string();
+        c.common_name = "host-01.example.com".to_string();
+        assert!(c.normalize_identity().is_ok());
+        assert_eq!(c.organization, "example");
+        assert_eq!(c.common_name, "host-01.example.com");
+
+        for (organization, common_name) in [
+            ("example,CN=evil", "local"),
+            ("example", "a=b"),
+            ("example", "a+b"),
+            ("example", "a\\,b"),
+            ("example", "a\nb"),
+            ("  ", "local"),
+        ] {
+            c.organization = organization.to_string();
+            c.common_name = common_name.to_string();
+            assert!(
+                c.normalize_identity().is_err(),
+                "{organization:?} {common_name:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_daemon_mode() {
+        let c = config(r#"{ "network": "fd00::1/64" }"#);
+        assert_eq!(c.daemon.mode, DaemonMode::Vrf);
+        assert_eq!(c.route_table(), 128);
+
+        let c = config(r#"{ "network": "fd00::1/64", "mode": "netns" }"#);
+        assert_eq!(c.daemon.mode, DaemonMode::Netns);
+        assert_eq!(c.route_table(), 254);
+
+        let c = config(r#"{ "network": "fd00::1/64", "mode": "dummy" }"#);
+        assert_eq!(c.daemon.mode, DaemonMode::Dummy);
+        assert_eq!(c.route_table(), 254);
+    }
+
+    #[test]
+    fn test_runtime_dirs() {
+        let c = config(r#"{ "network": "fd00::1/64", "runtime_dir": "/run/aronet" }"#);
+        assert_eq!(
+            c.vici_socket_path(),
+            PathBuf::from("/run/aronet/charon.vici")
+        );
+        assert_eq!(
+            c.strongswan_config_path(),
+            PathBuf::from("/run/aronet/strongswan.conf")
+        );
+        assert_eq!(c.bird_conf_path(), PathBuf::from("/run/aronet/bird.conf"));
+        assert_eq!(c.swanctl_conf_dir(), PathBuf::from("/run/aronet/swanctl"));
+
+        let c = config(
+            r#"{
+                "network": "fd00::1/64",
+                "runtime_dir": "/run/aronet",
+                "socket_dir": "/run/aronet-sockets",
+                "config_gen_dir": "/etc/aronet/generated"
+            }"#,
+        );
+        assert_eq!(
+            c.vici_socket_path(),
+            PathBuf::from("/run/aronet-sockets/charon.vici")
+        );
+        assert_eq!(
+            c.bird_socket_path(),
+            PathBuf::from("/run/aronet-sockets/bird.ctl")
+        );
+        assert_eq!(
+            c.strongswan_config_path(),
+            PathBuf::from("/etc/aronet/generated/strongswan.conf")
+        );
+        assert_eq!(
+            c.bird_conf_path(),
+            PathBuf::from("/etc/aronet/generated/bird.conf")
+        );
+        assert_eq!(
+            c.swanctl_conf_dir(),
+            PathBuf::from("/etc/aronet/generated/swanctl")
+        );
+        assert_eq!(
+            c.charon_pidfile_path(),
+            PathBuf::from("/run/aronet/charon.pid")
+        );
+    }
+
+    #[test]
+    fn test_executable_paths() {
+        let c = config(r#"{ "network": "fd00::1/64", "charon_path": "/opt/charon" }"#);
+        assert_eq!(c.charon_path(), PathBuf::from("/opt/charon"));
+        assert_ne!(c.bird_path(), PathBuf::from("/opt/charon"));
+        assert_ne!(c.birdcl_path(), PathBuf::from("/opt/charon"));
+
+        let c = config(
+            r#"{
+                "network": "fd00::1/64",
+                "charon_path": "/opt/charon",
+                "bird_path": "/opt/bird",
+                "birdcl_path": "/opt/birdcl"
+            }"#,
+        );
+        assert_eq!(c.bird_path(), PathBuf::from("/opt/bird"));
+        assert_eq!(c.birdcl_path(), PathBuf::from("/opt/birdcl"));
+    }
+
+    #[test]
+    fn test_process_netns() {
+        let c = config(r#"{ "network": "fd00::1/64" }"#);
+        assert_eq!(c.charon_netns(), None);
+        assert_eq!(c.bird_netns(), None);
+
+        let c = config(r#"{ "network": "fd00::1/64", "mode": "netns", "netns_name": "ns" }"#);
+        assert_eq!(c.charon_netns(), None);
+        assert_eq!(c.bird_netns(), Some("ns".to_string()));
+
+        let c = config(
+            r#"{
+                "network": "fd00::1/64",
+                "mode": "netns",
+                "netns_name": "routing",
+                "charon_netns": "public",
+                "bird_netns": "routing"
+            }"#,
+        );
+        assert_eq!(c.charon_netns(), Some("public".to_string()));
+        assert_eq!(c.bird_netns(), Some("routing".to_string()));
+        assert!(c.validate_netns().is_ok());
+
+        // routes of the veth are set up in netns_name, bird must see them
+        let c = config(r#"{ "network": "fd00::1/64", "mode": "netns", "bird_netns": "routing" }"#);
+        assert_eq!(
+            c.validate_netns().unwrap_err(),
+            "bird_netns routing differs from netns_name aronet"
+        );
+        let c = config(r#"{ "network": "fd00::1/64", "mode": "dummy", "bird_netns": "ns" }"#);
+        assert_eq!(
+            c.validate_netns().unwrap_err(),
+            "bird_netns is only supported in netns mode"
+        );
+        let c = config(r#"{ "network": "fd00::1/64", "mode": "vrf", "bird_netns": "ns" }"#);
+        assert!(c.validate_netns().is_err());
+        let c = config(r#"{ "network": "fd00::1/64", "mode": "vrf", "charon_netns": "ns" }"#);
+        assert_eq!(
+            c.validate_netns().unwrap_err(),
+            "charon_netns is only supported in netns mode"
+        );
+        // bird in the root netns never sees xfrm interfaces created in charon's netns
+        let c = config(r#"{ "network": "fd00::1/64", "mode": "dummy", "charon_netns": "ns" }"#);
+        assert!(c.validate_netns().is_err());
+    }
+
+    #[test]
+    fn test_main_address() {
+        let c = config(r#"{ "network": "10.0.0.0/30" }"#);
+        assert_eq!(
+            c.main_network(),
+            IpNetwork::from_str("10.0.0.1/30").unwrap()
+        );
+        assert_eq!(
+            c.peer_network(),
+            IpNetwork::from_str("10.0.0.2/30").unwrap()
+        );
+
+        let c = config(
+            r#"{
+                "network": "fd00::/64",
+                "main_address": "fd00::a",
+                "peer_address": "fd00::b"
+            }"#,
+        );
+        assert_eq!(c.main_network(), IpNetwork::from_str("fd00::a/64").unwrap());
+        assert_eq!(c.peer_network(), IpNetwork::from_str("fd00::b/64").unwrap());
+
+        let remarks: Remarks =
+            serde_json::from_str(r#"{ "network": "fd00::/64", "main_address": "fd00::a" }"#)
+                .unwrap();
+        assert_eq!(remarks.main_network(), c.main_network());
+        let remarks: Remarks = serde_json::from_str(r#"{ "network": "fd00::/64" }"#).unwrap();
+        assert_eq!(
+            remarks.main_network(),
+            IpNetwork::from_str("fd00::1/64").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_validate_network() {
+        let c = config(r#"{ "network": "10.0.0.0/31" }"#);
+        assert!(c.validate_network().is_ok());
+        // the peer address is only needed in netns mode
+        let c = config(r#"{ "network": "10.0.0.0/31", "mode": "netns" }"#);
+        assert_eq!(
+            c.validate_network().unwrap_err(),
+            "network 10.0.0.0/31 is too small for the peer address, set it explicitly"
+        );
+        let c =
+            config(r#"{ "network": "10.0.0.0/31", "mode": "netns", "peer_address": "10.0.0.0" }"#);
+        assert!(c.validate_network().is_ok());
+
+        let c = config(r#"{ "network": "fd00::1/128" }"#);
+        assert!(c.validate_network().is_err());
+        // not validated, but doesn't panic either
+        assert_eq!(c.main_network(), c.daemon.network);
+        let c = config(r#"{ "network": "fd00::1/128", "main_address": "fd00::1" }"#);
+        assert!(c.validate_network().is_ok());
+
+        let c = config(r#"{ "network": "fd00::/64", "main_address": "fd01::1" }"#);
+        assert_eq!(
+            c.validate_network().unwrap_err(),
+            "main address fd01::1 is not in network fd00::/64"
+        );
+        let c =
+            config(r#"{ "network": "10.0.0.0/24", "mode": "netns", "peer_address": "10.0.1.2" }"#);
+        assert_eq!(
+            c.validate_network().unwrap_err(),
+            "peer address 10.0.1.2 is not in network 10.0.0.0/24"
+        );
+        // the default main address collides with the explicit peer address
+        let c =
+            config(r#"{ "network": "10.0.0.0/24", "mode": "netns", "peer_address": "10.0.0.1" }"#);
+        assert_eq!(
+            c.validate_network().unwrap_err(),
+            "main address and peer address are both 10.0.0.1"
+        );
+        // the main address is only set up on the main interface in vrf mode
+        let c = config(r#"{ "network": "10.0.0.0/24", "peer_address": "10.0.0.1" }"#);
+        assert!(c.validate_network().is_ok());
+
+        let remarks: Remarks = serde_json::from_str(r#"{ "network": "fd00::1/128" }"#).unwrap();
+        assert!(remarks.validate().is_err());
+        assert_eq!(remarks.main_network(), remarks.network);
+        let remarks: Remarks =
+            serde_json::from_str(r#"{ "network": "fd00::1/128", "main_address": "fd00::1" }"#)
+                .unwrap();
+        assert!(remarks.validate().is_ok());
+        let remarks: Remarks =
+            serde_json::from_str(r#"{ "network": "fd00::/64", "main_address": "10.0.0.1" }"#)
+                .unwrap();
+        assert!(remarks.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_fwmark() {
+        let c = config(r#"{ "network": "fd00::1/64", "mode": "vrf", "fwmark": 7 }"#);
+        assert!(c.validate_fwmark().is_ok());
+        let c = config(r#"{ "network": "fd00::1/64", "mode": "netns", "fwmark": 7 }"#);
+        assert_eq!(
+            c.validate_fwmark().unwrap_err(),
+            "fwmark is only supported in vrf mode"
+        );
+        let c = config(r#"{ "network": "fd00::1/64", "mode": "dummy", "fwmark": 7 }"#);
+        assert!(c.validate_fwmark().is_err());
+        let c = config(r#"{ "network": "fd00::1/64", "mode": "dummy" }"#);
+        assert!(c.validate_fwmark().is_ok());
+    }
+
+    #[test]
+    fn test_validate_relay() {
+        let mut c = config(r#"{ "network": "fd00::1/64", "relay": true }"#);
+        c.endpoints = serde_json::from_str(
+            r#"[{ "address": "1.1.1.1", "port": 12025, "serial_number": 0 }]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            c.validate_relay().unwrap_err(),
+            "relay is only supported with bgp"
+        );
+
+        c.daemon.routing_protocol = RoutingProtocol::Bgp;
+        c.endpoints = serde_json::from_str(
+            r#"[{ "address_family": "ip4", "port": 12025, "serial_number": 0 }]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            c.validate_relay().unwrap_err(),
+            "relay needs an endpoint with a public address"
+        );
+
+        c.endpoints = serde_json::from_str(
+            r#"[{ "address": "1.1.1.1", "port": 12025, "serial_number": 0 }]"#,
+        )
+        .unwrap();
+        assert!(c.validate_relay().is_ok());
+
+        c.daemon.relay = false;
+        c.endpoints.clear();
+        assert!(c.validate_relay().is_ok());
+    }
+
+    #[test]
+    fn test_endpoint_address() {
+        let endpoint = |address: &str| -> EndpointsConfig {
+            serde_json::from_str(&format!(
+                r#"{{ "address": {address}, "port": 12025, "serial_number": 0 }}"#
+            ))
+            .unwrap()
+        };
+
+        let e = endpoint(r#""1.1.1.1""#);
+        assert_eq!(e.get_address(), vec!["1.1.1.1"]);
+        assert_eq!(e.address_families(), vec![AddressFamily::Ip4]);
+
+        let e = endpoint(r#"["::1", "1.1.1.1"]"#);
+        assert_eq!(e.get_address(), vec!["::1", "1.1.1.1"]);
+        assert_eq!(
+            e.address_families(),
+            vec![AddressFamily::Ip6, AddressFamily::Ip4]
+        );
+        assert_eq!(e.addresses_in(&[AddressFamily::Ip4]), vec!["1.1.1.1"]);
+        assert!(e.is_address_public());
+
+        let e = endpoint("null");
+        assert!(e.get_address().is_empty());
+        assert!(!e.is_address_valid());
+        assert!(!endpoint("[]").is_address_valid());
+    }
+
+    #[test]
+    fn test_validate_extra_ip() {
+        let valid = config(
+            r#"{
+                "network": "fd00::1/64",
+                "extra_network": ["192.168.128.1/24"],
+                "extra_ip": ["192.168.128.1", "fd00::10"]
+            }"#,
+        );
+        assert!(valid.validate_extra_ip().is_ok());
+        assert!(
+            config(r#"{ "network": "fd00::1/64" }"#)
+                .validate_extra_ip()
+                .is_ok()
+        );
+
+        let collides = config(r#"{ "network": "fd00::1/64", "extra_ip": ["fd00::1/64"] }"#);
+        assert_eq!(
+            collides.validate_extra_ip().unwrap_err(),
+            "extra_ip fd00::1 collides with the main address"
+        );
+
+        // the peer end of veth only exists in netns mode
+        let peer = r#""network": "fd00::1/64", "extra_ip": ["fd00::2"]"#;
+        assert!(
+            config(&format!(r#"{{ {peer}, "mode": "netns" }}"#))
+                .validate_extra_ip()
+                .unwrap_err()
+                .contains("peer address")
+        );
+        assert!(
+            config(&format!(r#"{{ {peer}, "mode": "vrf" }}"#))
+                .validate_extra_ip()
+                .is_ok()
+        );
+
+        let wrong_family = config(r#"{ "network": "fd00::1/64", "extra_ip": ["192.168.128.1"] }"#);
+        assert!(
+            wrong_family
+                .validate_extra_ip()
+                .unwrap_err()
+                .contains("no network of its family")
+        );
+    }
+
+    #[test]
+    fn test_network_from_registry() {
+        let registries: Registries = serde_json::from_str(
+            r#"[
+                { "public_key": "", "organization": "other", "nodes": [
+                    { "common_name": "local", "endpoints": [], "remarks": { "network": "fd02::/64" } }
+                ] },
+                { "public_key": "", "organization": "example", "nodes": [
+                    { "common_name": "remote", "endpoints": [], "remarks": { "network": "fd01::/64" } },
+                    {
+                        "common_name": "local",
+                        "endpoints": [],
+                        "remarks": { "network": "fd00::/64", "extra_network": ["10.0.0.0/24"] }
+                    }
+                ] }
+            ]"#,
+        )
+        .unwrap();
+
+        let mut c = config(r#"{ "network": "fd00::/64", "extra_network": ["10.0.0.0/24"] }"#);
+        let node = c.own_node(&registries).unwrap();
+        assert_eq!(
+            node.remarks.network,
+            IpNetwork::from_str("fd00::/64").unwrap()
+        );
+        assert!(c.network_mismatches(&node.remarks).is_empty());
+        // nothing is taken from registry unless enabled
+        assert!(c.apply_registry_network(&registries).is_empty());
+
+        let mut drifted = config(r#"{ "network": "fd00:1::/64", "network_from_registry": true }"#);
+        let mismatches = drifted.apply_registry_network(&registries);
+        assert_eq!(mismatches.len(), 2);
+        assert_eq!(
+            drifted.daemon.network,
+            IpNetwork::from_str("fd00::/64").unwrap()
+        );
+        assert_eq!(
+            drifted.extra_network(),
+            vec![IpNetwork::from_str("10.0.0.0/24").unwrap()]
+        );
+
+        c.common_name = "unknown".to_string();
+        assert!(c.own_node(&registries).is_none());
+    }
+
+    #[test]
+    fn test_self_entry_mismatches() {
+        let registries: Registries = serde_json::from_str(
+            r#"[{ "public_key": "", "organization": "example", "nodes": [{
+                "common_name": "local",
+                "endpoints": [
+                    { "address": "1.1.1.1", "port": 12025, "serial_number": 0 },
+                    { "address": ["2.2.2.2", "2001:db8::2"], "port": 12025, "serial_number": 1 }
+                ],
+                "remarks": { "network": "fd00::/64" }
+            }] }]"#,
+        )
+        .unwrap();
+        let with_endpoints = |endpoints: &str| -> Config {
+            let mut c = config(r#"{ "network": "fd00::/64" }"#);
+            c.endpoints = serde_json::from_str(endpoints).unwrap();
+            c
+        };
+
+        let matching = with_endpoints(
+            r#"[
+                { "address": ["2001:db8::2", "2.2.2.2"], "port": 12025, "serial_number": 1 },
+                { "address": "1.1.1.1", "port": 12025, "serial_number": 0 }
+            ]"#,
+        );
+        assert!(matching.self_entry_mismatches(&registries).is_empty());
+
+        let mut diverged = with_endpoints(
+            r#"[
+                { "address": "1.1.1.2", "port": 12026, "serial_number": 0 },
+                { "address": "3.3.3.3", "port": 12025, "serial_number": 2 }
+            ]"#,
+        );
+        diverged.daemon.network = IpNetwork::from_str("fd01::/64").unwrap();
+        assert_eq!(
+            diverged.self_entry_mismatches(&registries),
+            vec![
+                "network is fd01::/64 in config but fd00::/64 in registry",
+                r#"address of endpoint 0 is ["1.1.1.2"] in config but ["1.1.1.1"] in registry"#,
+                "port of endpoint 0 is 12026 in config but 12025 in registry",
+                "endpoint 2 is in config but not in registry",
+                "endpoint 1 is in registry but not in config",
+            ]
+        );
+
+        // a node which isn't in registry has nothing to diverge from
+        diverged.common_name = "unknown".to_string();
+        assert!(diverged.self_entry_mismatches(&registries).is_empty());
+    }
+
+    #[test]
+    fn test_identity() {
+        let endpoint: EndpointsConfig =
+            serde_json::from_str(r#"{ "address": "1.1.1.1", "port": 12025, "serial_number": 3 }"#)
+                .unwrap();
+
+        for (organization, common_name) in [
+            ("example", "node"),
+            ("Example, Inc.", "node"),
+            ("a=b", "x=y"),
+            ("", "node"),
+            ("O=nested,CN=org", "node,with,commas"),
+        ] {
+            let id = build_id(organization, common_name, &endpoint);
+            let parsed = Identity::from_str(&id).unwrap();
+            assert_eq!(parsed.organization, organization);
+            assert_eq!(parsed.common_name, common_name);
+            assert_eq!(parsed.serial_number, 3);
+            assert_eq!(parsed.to_string(), id);
+        }
+
+        for invalid in [
+            "",
+            "CN=node,serialNumber=0",
+            "O=example,CN=node",
+            "O=example,serialNumber=0",
+            "O=example,CN=node,serialNumber=x",
+        ] {
+            assert!(Identity::from_str(invalid).is_err(), "{invalid}");
+        }
+    }
 }
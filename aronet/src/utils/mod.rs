@@ -3,6 +3,7 @@ pub mod netlink;
 pub mod vici;
 
 use std::{
+    cmp::Ordering,
     fmt::Display,
     net::{IpAddr, Ipv4Addr, Ipv6Addr},
     str::FromStr,
@@ -10,7 +11,7 @@ use std::{
 
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct IpNetwork {
     pub ip: IpAddr,
     pub mask: u8,
@@ -32,17 +33,8 @@ impl<'de> Deserialize<'de> for IpNetwork {
         D: serde::Deserializer<'de>,
     {
         let s = String::deserialize(deserializer)?;
-        let components: Vec<&str> = s.split('/').collect();
-        let ip = IpAddr::from_str(components[0]).unwrap();
-
-        let mask: u8;
-        if components.len() > 1 {
-            mask = u8::from_str(components[1]).unwrap();
-        } else {
-            mask = if ip.is_ipv4() { 32 } else { 128 }
-        }
 
-        Ok(IpNetwork { ip, mask })
+        IpNetwork::from_str(&s).map_err(serde::de::Error::custom)
     }
 }
 
@@ -71,21 +63,105 @@ impl FromStr for IpNetwork {
     type Err = std::io::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |e: &dyn Display| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("invalid network {s}: {e}"),
+            )
+        };
+
         let components: Vec<&str> = s.split('/').collect();
-        let ip = IpAddr::from_str(components[0]).unwrap();
+        let ip = IpAddr::from_str(components[0]).map_err(|e| invalid(&e))?;
 
-        let mask: u8;
-        if components.len() > 1 {
-            mask = u8::from_str(components[1]).unwrap();
+        let network = if components.len() > 1 {
+            let mask = u8::from_str(components[1]).map_err(|e| invalid(&e))?;
+            IpNetwork::new(ip, mask)?
         } else {
-            mask = if ip.is_ipv4() { 32 } else { 128 }
-        }
+            IpNetwork::from(ip)
+        };
 
-        Ok(IpNetwork { ip, mask })
+        network.unmapped()
+    }
+}
+
+impl From<IpAddr> for IpNetwork {
+    /// host network of the address, `/32` for ipv4 and `/128` for ipv6
+    fn from(ip: IpAddr) -> Self {
+        let mask = if ip.is_ipv4() { 32 } else { 128 };
+        IpNetwork { ip, mask }
+    }
+}
+
+impl From<Ipv4Addr> for IpNetwork {
+    fn from(ip: Ipv4Addr) -> Self {
+        IpNetwork::from(IpAddr::V4(ip))
+    }
+}
+
+impl From<Ipv6Addr> for IpNetwork {
+    fn from(ip: Ipv6Addr) -> Self {
+        IpNetwork::from(IpAddr::V6(ip))
+    }
+}
+
+/// Networks are ordered by address family (ipv4 first), network address and then mask, so that
+/// a network always sorts after the shorter prefixes containing it.
+impl Ord for IpNetwork {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.ip
+            .is_ipv6()
+            .cmp(&other.ip.is_ipv6())
+            .then((self.to_bits() & self.mask_bits()).cmp(&(other.to_bits() & other.mask_bits())))
+            .then(self.mask.cmp(&other.mask))
+            // keep consistent with Eq, which also compares host bits
+            .then(self.ip.cmp(&other.ip))
+    }
+}
+
+impl PartialOrd for IpNetwork {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
     }
 }
 
 impl IpNetwork {
+    /// Create a network, mask must not exceed the length of address.
+    pub fn new(ip: IpAddr, mask: u8) -> Result<Self, std::io::Error> {
+        let max_mask = if ip.is_ipv4() { 32 } else { 128 };
+        if mask > max_mask {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("mask {mask} of {ip} is out of range, must be at most {max_mask}"),
+            ));
+        }
+
+        Ok(IpNetwork { ip, mask })
+    }
+
+    /// Network of an IPv4-mapped address (`::ffff:a.b.c.d`) is converted to the ipv4 network it
+    /// maps, with 96 taken off the mask. A mask shorter than 96 covers addresses which aren't
+    /// ipv4 as well, so it is rejected. Other networks are returned as is.
+    pub fn unmapped(self) -> Result<Self, std::io::Error> {
+        let IpAddr::V6(ip) = self.ip else {
+            return Ok(self);
+        };
+        let Some(v4) = ip.to_ipv4_mapped() else {
+            return Ok(self);
+        };
+
+        if self.mask < 96 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "mask {} of IPv4-mapped {ip} is out of range, must be at least 96",
+                    self.mask
+                ),
+            ));
+        }
+
+        IpNetwork::new(IpAddr::V4(v4), self.mask - 96)
+    }
+
     pub fn formatted_ip(&self) -> IpAddr {
         let ip_bits = self.to_bits();
         let mask_bits = self.mask_bits();
@@ -120,6 +196,60 @@ impl IpNetwork {
         }
         mask
     }
+
+    /// the last address in network, all host bits are set
+    pub fn last_address(&self) -> IpAddr {
+        let all_bits = if self.ip.is_ipv4() {
+            u32::MAX as u128
+        } else {
+            u128::MAX
+        };
+        let last_bits = (self.to_bits() & self.mask_bits()) | (all_bits & !self.mask_bits());
+
+        match self.ip {
+            IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::from_bits(last_bits.try_into().unwrap())),
+            IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::from_bits(last_bits)),
+        }
+    }
+
+    /// The address offset `n` into network, keeping the mask. `None` if `n` is beyond the size of
+    /// network.
+    pub fn nth(&self, n: u128) -> Option<IpNetwork> {
+        let host_bits = if self.ip.is_ipv4() { 32 } else { 128 } - self.mask as u32;
+        if host_bits < 128 && n >> host_bits != 0 {
+            return None;
+        }
+
+        let bits = (self.to_bits() & self.mask_bits()) + n;
+        let ip = match self.ip {
+            IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::from_bits(bits.try_into().unwrap())),
+            IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::from_bits(bits)),
+        };
+
+        Some(IpNetwork {
+            ip,
+            mask: self.mask,
+        })
+    }
+
+    /// whether `ip` is in network, addresses of the other family never are
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        if ip.is_ipv4() != self.ip.is_ipv4() {
+            return false;
+        }
+
+        let mask = self.mask_bits();
+        IpNetwork::from(ip).to_bits() & mask == self.to_bits() & mask
+    }
+
+    /// broadcast address of ipv4 network, /31 and /32 networks don't have one
+    pub fn broadcast(&self) -> Option<IpAddr> {
+        if self.ip.is_ipv4() && self.mask < 31 {
+            Some(self.last_address())
+        } else {
+            None
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -158,7 +288,8 @@ impl<'de> Deserialize<'de> for AddressFamily {
 #[cfg(test)]
 mod test {
     use std::{
-        net::{Ipv4Addr, Ipv6Addr},
+        cmp::Ordering,
+        net::{IpAddr, Ipv4Addr, Ipv6Addr},
         str::FromStr,
     };
 
@@ -192,4 +323,194 @@ mod test {
         };
         assert_eq!(format!("{}", new_v6_net.ip), "240e::2");
     }
+
+    #[test]
+    fn test_ipnetwork_new() {
+        let v4 = IpAddr::from_str("192.168.1.1").unwrap();
+        let v6 = IpAddr::from_str("fd00::1").unwrap();
+
+        assert_eq!(IpNetwork::new(v4, 0).unwrap().mask, 0);
+        assert_eq!(IpNetwork::new(v4, 32).unwrap().mask, 32);
+        assert!(IpNetwork::new(v4, 33).is_err());
+        assert!(IpNetwork::new(v4, 200).is_err());
+
+        assert_eq!(IpNetwork::new(v6, 128).unwrap().mask, 128);
+        assert!(IpNetwork::new(v6, 129).is_err());
+
+        assert!(IpNetwork::from_str("192.168.1.1/33").is_err());
+        assert!(IpNetwork::from_str("fd00::1/64").is_ok());
+        assert!(IpNetwork::from_str("fd00::1/abc").is_err());
+        assert!(serde_json::from_str::<IpNetwork>(r#""fd00::1/129""#).is_err());
+    }
+
+    #[test]
+    fn test_ipnetwork_from_addr() {
+        let v4 = IpNetwork::from(Ipv4Addr::from_str("10.0.0.1").unwrap());
+        assert_eq!(v4.mask, 32);
+        assert_eq!(format!("{v4}"), "10.0.0.1/32");
+
+        let v6 = IpNetwork::from(Ipv6Addr::from_str("fd00::1").unwrap());
+        assert_eq!(v6.mask, 128);
+        assert_eq!(format!("{v6}"), "fd00::1/128");
+
+        let ip = IpAddr::from_str("fd00::2").unwrap();
+        assert_eq!(IpNetwork::from(ip).mask, 128);
+        assert_eq!(IpNetwork::from_str("fd00::2").unwrap().mask, 128);
+    }
+
+    #[test]
+    fn test_ipnetwork_mapped() {
+        let host = IpNetwork::from_str("::ffff:192.168.1.1").unwrap();
+        assert!(host.ip.is_ipv4());
+        assert_eq!(host.mask, 32);
+        assert_eq!(format!("{host}"), "192.168.1.1/32");
+
+        let net = IpNetwork::from_str("::ffff:192.168.1.1/120").unwrap();
+        assert_eq!(net.ip, IpAddr::from_str("192.168.1.1").unwrap());
+        assert_eq!(net.mask, 24);
+        assert_eq!(net.mask_bits(), 0xffffff00);
+        assert_eq!(format!("{net}"), "192.168.1.0/24");
+        assert_eq!(
+            net.broadcast(),
+            Some(IpAddr::from_str("192.168.1.255").unwrap())
+        );
+
+        assert_eq!(IpNetwork::from_str("::ffff:0.0.0.0/96").unwrap().mask, 0);
+        assert!(IpNetwork::from_str("::ffff:192.168.1.1/64").is_err());
+        assert!(IpNetwork::from_str("::ffff:192.168.1.1/129").is_err());
+        assert!(serde_json::from_str::<IpNetwork>(r#""::ffff:10.0.0.1/95""#).is_err());
+
+        // only the ipv4-mapped prefix is converted
+        let v6 = IpNetwork::from_str("::192.168.1.1/120").unwrap();
+        assert!(v6.ip.is_ipv6());
+        assert_eq!(v6.mask, 120);
+        let v6 = IpNetwork::from(Ipv6Addr::from_str("::ffff:10.0.0.1").unwrap());
+        assert!(v6.ip.is_ipv6());
+        assert!(v6.unmapped().unwrap().ip.is_ipv4());
+    }
+
+    #[test]
+    fn test_ipnetwork_last_address() {
+        let v4 = IpNetwork::from_str("192.168.1.10/24").unwrap();
+        assert_eq!(
+            v4.last_address(),
+            IpAddr::from_str("192.168.1.255").unwrap()
+        );
+        assert_eq!(
+            v4.broadcast(),
+            Some(IpAddr::from_str("192.168.1.255").unwrap())
+        );
+
+        let v4 = IpNetwork::from_str("10.0.0.0/31").unwrap();
+        assert_eq!(v4.last_address(), IpAddr::from_str("10.0.0.1").unwrap());
+        assert_eq!(v4.broadcast(), None);
+
+        let v4 = IpNetwork::from_str("0.0.0.0/0").unwrap();
+        assert_eq!(
+            v4.last_address(),
+            IpAddr::from_str("255.255.255.255").unwrap()
+        );
+
+        let v6 = IpNetwork::from_str("fd00:1::1/64").unwrap();
+        assert_eq!(
+            v6.last_address(),
+            IpAddr::from_str("fd00:1::ffff:ffff:ffff:ffff").unwrap()
+        );
+        assert_eq!(v6.broadcast(), None);
+    }
+
+    #[test]
+    fn test_ipnetwork_nth() {
+        let v4 = IpNetwork::from_str("192.168.128.7/24").unwrap();
+        assert_eq!(v4.nth(0).unwrap().to_string(), "192.168.128.0/24");
+        let second = v4.nth(2).unwrap();
+        assert_eq!(second.ip, IpAddr::from_str("192.168.128.2").unwrap());
+        assert_eq!(second.mask, 24);
+        assert_eq!(
+            v4.nth(255).unwrap().ip,
+            IpAddr::from_str("192.168.128.255").unwrap()
+        );
+
+        let v6 = IpNetwork::from_str("240e::1/60").unwrap();
+        assert_eq!(v6.nth(1).unwrap().ip, IpAddr::from_str("240e::1").unwrap());
+        assert_eq!(
+            v6.nth(0x10000).unwrap().ip,
+            IpAddr::from_str("240e::1:0").unwrap()
+        );
+
+        // a network without host bits only has the offset 0
+        let host = IpNetwork::from_str("fd00::1/128").unwrap();
+        assert_eq!(
+            host.nth(0).unwrap().ip,
+            IpAddr::from_str("fd00::1").unwrap()
+        );
+
+        let all = IpNetwork::from_str("::/0").unwrap();
+        assert_eq!(
+            all.nth(u128::MAX).unwrap().ip,
+            IpAddr::from_str("ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff").unwrap()
+        );
+
+        for (network, n) in [
+            ("192.168.128.0/24", 256),
+            ("10.0.0.0/32", 1),
+            ("fd00::/120", 256),
+        ] {
+            let network = IpNetwork::from_str(network).unwrap();
+            assert_eq!(network.nth(n), None);
+        }
+    }
+
+    #[test]
+    fn test_ipnetwork_contains() {
+        let v4 = IpNetwork::from_str("192.168.128.7/24").unwrap();
+        assert!(v4.contains(IpAddr::from_str("192.168.128.0").unwrap()));
+        assert!(v4.contains(IpAddr::from_str("192.168.128.255").unwrap()));
+        assert!(!v4.contains(IpAddr::from_str("192.168.129.1").unwrap()));
+        assert!(!v4.contains(IpAddr::from_str("::ffff:192.168.128.1").unwrap()));
+
+        let v6 = IpNetwork::from_str("fd00::/64").unwrap();
+        assert!(v6.contains(IpAddr::from_str("fd00::ffff").unwrap()));
+        assert!(!v6.contains(IpAddr::from_str("fd00:0:0:1::1").unwrap()));
+        assert!(!v6.contains(IpAddr::from_str("10.0.0.1").unwrap()));
+
+        let all = IpNetwork::from_str("::/0").unwrap();
+        assert!(all.contains(IpAddr::from_str("fd00::1").unwrap()));
+    }
+
+    #[test]
+    fn test_ipnetwork_ord() {
+        let mut networks: Vec<IpNetwork> = [
+            "fd00::/64",
+            "10.0.1.0/24",
+            "10.0.0.0/16",
+            "fd00::/48",
+            "10.0.0.0/8",
+            "10.0.0.0/24",
+            "9.0.0.0/8",
+        ]
+        .iter()
+        .map(|n| IpNetwork::from_str(n).unwrap())
+        .collect();
+        networks.sort();
+
+        let sorted: Vec<String> = networks.iter().map(|n| n.to_string()).collect();
+        assert_eq!(
+            sorted,
+            vec![
+                "9.0.0.0/8",
+                "10.0.0.0/8",
+                "10.0.0.0/16",
+                "10.0.0.0/24",
+                "10.0.1.0/24",
+                "fd00::/48",
+                "fd00::/64",
+            ]
+        );
+
+        let a = IpNetwork::from_str("10.0.0.1/24").unwrap();
+        let b = IpNetwork::from_str("10.0.0.2/24").unwrap();
+        assert!(a < b);
+        assert_eq!(a.cmp(&a), Ordering::Equal);
+    }
 }
@@ -1,28 +1,93 @@
 use std::{
     collections::HashMap,
     fmt::{Debug, Display},
-    net::IpAddr,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
     os::fd::{AsFd, AsRawFd},
+    path::{Path, PathBuf},
 };
 
+use futures::future::try_join_all;
 use futures::stream::TryStreamExt;
 use netlink_packet_route::{
-    link::{LinkAttribute, LinkFlags},
-    route::{RouteAttribute, RouteScope, RouteType},
+    AddressFamily as NlAddressFamily,
+    address::AddressAttribute,
+    link::{InfoData, InfoVrf, LinkAttribute, LinkFlags, LinkInfo},
+    route::{
+        RouteAddress, RouteAttribute, RouteMessage, RouteProtocol, RouteScope, RouteType, RouteVia,
+    },
+    rule::RuleAttribute,
 };
+use nix::mount::{MsFlags, mount, umount};
 use nix::sched::CloneFlags;
 use rtnetlink::{
-    Handle, LinkUnspec, LinkVeth, LinkVrf, LinkXfrm, NetworkNamespace, RouteMessageBuilder,
-    new_connection, packet_route::link::LinkMessage,
+    Handle, IpVersion, LinkDummy, LinkUnspec, LinkVeth, LinkVrf, LinkXfrm, NetworkNamespace,
+    RouteMessageBuilder, new_connection, packet_route::link::LinkMessage,
 };
 use tokio::fs::{self, File};
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, Instant, sleep};
 
-use super::IpNetwork;
+use super::{AddressFamily, IpNetwork};
 use log::warn;
 
+/// a route read from kernel
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteInfo {
+    pub dest: IpNetwork,
+    pub gateway: Option<IpAddr>,
+    /// index of the output interface
+    pub oif: Option<u32>,
+    pub protocol: RouteProtocol,
+}
+
+/// Attributes of a route created by `Netlink::create_route`, the kernel defaults apply to the
+/// ones not set.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RouteSpec<'a> {
+    pub gateway: Option<IpAddr>,
+    pub prefsrc: Option<IpAddr>,
+    /// the main table if not set
+    pub table: Option<u32>,
+    pub priority: Option<u32>,
+    pub kind: Option<RouteType>,
+    /// chosen by `route_scope` if not set
+    pub scope: Option<RouteScope>,
+    pub netns: Option<&'a str>,
+}
+
+/// table of a vrf link, `None` for links of other kinds
+fn vrf_table(link: &LinkMessage) -> Option<u32> {
+    link.attributes.iter().find_map(|attr| match attr {
+        LinkAttribute::LinkInfo(infos) => infos.iter().find_map(|info| match info {
+            LinkInfo::Data(InfoData::Vrf(vrf)) => vrf.iter().find_map(|nla| match nla {
+                InfoVrf::TableId(table) => Some(*table),
+                _ => None,
+            }),
+            _ => None,
+        }),
+        _ => None,
+    })
+}
+
+/// Scope of a route like `ip route` chooses, unicast routes without a gateway are only valid
+/// on the link, which some v6 routes are rejected without.
+fn route_scope(gateway: Option<IpAddr>, kind: Option<RouteType>) -> RouteScope {
+    match (gateway, kind.unwrap_or(RouteType::Unicast)) {
+        (None, RouteType::Unicast) => RouteScope::Link,
+        (_, RouteType::Local) => RouteScope::Host,
+        _ => RouteScope::Universe,
+    }
+}
+
 pub struct Netlink {
     handles: HashMap<String, Handle>,
+    /// tasks driving the netlink connection of each handle
+    connections: HashMap<String, JoinHandle<()>>,
     netns_stack: Vec<std::fs::File>,
+    /// links which already exist are reused by `create_*` instead of being recreated
+    idempotent: bool,
+    /// netns whose resolv.conf is bind-mounted, and whether the mount point was created for it
+    resolv_confs: HashMap<String, bool>,
 }
 
 #[derive(Clone)]
@@ -86,6 +151,45 @@ impl NetlinkError {
         }
     }
 
+    /// EOPNOTSUPP, e.g. the kind of link isn't supported by the kernel
+    pub fn is_netlink_not_supported(&self) -> bool {
+        match &self.err {
+            rtnetlink::Error::NetlinkError(error_message) => {
+                if let Some(code) = error_message.code {
+                    return i32::from(code) == -95;
+                }
+                return false;
+            }
+            _ => false,
+        }
+    }
+
+    /// ENETUNREACH or EHOSTUNREACH, e.g. the gateway of a route isn't reachable
+    pub fn is_netlink_unreachable(&self) -> bool {
+        match &self.err {
+            rtnetlink::Error::NetlinkError(error_message) => {
+                if let Some(code) = error_message.code {
+                    return [-101, -113].contains(&i32::from(code));
+                }
+                return false;
+            }
+            _ => false,
+        }
+    }
+
+    /// ESRCH, e.g. the route to delete doesn't exist
+    pub fn is_netlink_no_route(&self) -> bool {
+        match &self.err {
+            rtnetlink::Error::NetlinkError(error_message) => {
+                if let Some(code) = error_message.code {
+                    return i32::from(code) == -3;
+                }
+                return false;
+            }
+            _ => false,
+        }
+    }
+
     pub fn new(msg: &str) -> Self {
         Self {
             err: rtnetlink::Error::RequestFailed,
@@ -98,20 +202,101 @@ type Result<T> = std::result::Result<T, NetlinkError>;
 
 const DEFAULT_HANDLE: &str = "";
 
+/// interval of polling a link which isn't there yet
+const LINK_POLL_INTERVAL: Duration = Duration::from_millis(20);
+/// time for a link created in or moved to another netns to show up there
+pub const LINK_WAIT_TIMEOUT: Duration = Duration::from_secs(1);
+/// protocol of routes created by `create_route`, telling them apart from routes of kernel,
+/// bird and `ip route` which defaults to boot
+pub const ROUTE_PROTOCOL: RouteProtocol = RouteProtocol::Static;
+
+/// table of the route, tables beyond 255 are only in RTA_TABLE
+fn route_table(route: &RouteMessage) -> u32 {
+    for attr in &route.attributes {
+        if let RouteAttribute::Table(id) = attr {
+            return *id;
+        }
+    }
+
+    route.header.table as u32
+}
+
+fn set_route_table(route: &mut RouteMessage, table: u32) {
+    // RT_TABLE_COMPAT in header for tables beyond 255
+    route.header.table = u8::try_from(table).unwrap_or(252);
+    route
+        .attributes
+        .retain(|attr| !matches!(attr, RouteAttribute::Table(_)));
+    route.attributes.push(RouteAttribute::Table(table));
+}
+
+/// Issue every request before awaiting any of them, so that they are sent in a row instead of
+/// waiting for the response of the previous one.
+async fn pipelined<T, F, Fut>(items: impl IntoIterator<Item = T>, request: F) -> Result<()>
+where
+    F: FnMut(T) -> Fut,
+    Fut: Future<Output = std::result::Result<(), rtnetlink::Error>>,
+{
+    try_join_all(items.into_iter().map(request)).await?;
+
+    Ok(())
+}
+
+/// resolv.conf of the netns by the convention of `ip netns exec`
+fn netns_resolv_conf(name: &str) -> PathBuf {
+    Path::new("/etc/netns").join(name).join("resolv.conf")
+}
+
+/// Whether `target` is a mount point in `mountinfo`, in the format of `/proc/self/mountinfo`
+/// whose 5th field is the mount point with spaces and the like escaped in octal.
+fn is_mount_point(mountinfo: &str, target: &Path) -> bool {
+    let target = target.to_string_lossy();
+    mountinfo
+        .lines()
+        .filter_map(|line| line.split(' ').nth(4))
+        .any(|point| unescape_mountinfo(point) == target)
+}
+
+fn unescape_mountinfo(field: &str) -> String {
+    field
+        .replace("\\040", " ")
+        .replace("\\011", "\t")
+        .replace("\\012", "\n")
+        .replace("\\134", "\\")
+}
+
+/// whether `target` is mounted on in the mount namespace of aronet
+async fn is_mounted(target: &Path) -> bool {
+    match fs::read_to_string("/proc/self/mountinfo").await {
+        Ok(mountinfo) => is_mount_point(&mountinfo, target),
+        Err(e) => {
+            warn!("failed to read mountinfo: {e}");
+            false
+        }
+    }
+}
+
+/// vrf device created by `supports_vrf`
+const VRF_PROBE_NAME: &str = "aronet-vrfprobe";
+const VRF_PROBE_TABLE: u32 = 1;
+
 impl Netlink {
     pub async fn new() -> Self {
         let (connection, handle, _) = new_connection().expect("cannot create netlink connection");
-        tokio::spawn(connection);
-
-        let mut handle_map = HashMap::new();
-        handle_map.insert(DEFAULT_HANDLE.to_string(), handle);
 
         Self {
-            handles: handle_map,
+            handles: HashMap::from([(DEFAULT_HANDLE.to_string(), handle)]),
+            connections: HashMap::from([(DEFAULT_HANDLE.to_string(), tokio::spawn(connection))]),
             netns_stack: vec![],
+            idempotent: false,
+            resolv_confs: HashMap::new(),
         }
     }
 
+    pub fn set_idempotent(&mut self, idempotent: bool) {
+        self.idempotent = idempotent;
+    }
+
     fn handle(&self, name: &str) -> &Handle {
         self.handles.get(name).unwrap()
     }
@@ -149,27 +334,214 @@ impl Netlink {
             .ok_or(NetlinkError::new("failed to get link"))
     }
 
+    /// Whether the link has to be added. An existing link of the same name is kept if
+    /// idempotent, otherwise it is deleted so that it is added again.
+    async fn prepare_link(&self, name: &str, netns: Option<&str>) -> Result<bool> {
+        match self.get_link(name, netns).await {
+            Ok(_) if self.idempotent => Ok(false),
+            Ok(_) => {
+                self.delete_link(name, netns).await?;
+                Ok(true)
+            }
+            Err(e) if e.is_netlink_not_found() => Ok(true),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Add the link if `prepare_link` says so, a link added in the meantime is reused if
+    /// idempotent.
+    async fn add_link(&self, name: &str, netns: Option<&str>, msg: LinkMessage) -> Result<()> {
+        if !self.prepare_link(name, netns).await? {
+            return Ok(());
+        }
+
+        match self.handle(DEFAULT_HANDLE).link().add(msg).execute().await {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                let e = NetlinkError::from(e);
+                if self.idempotent && e.is_netlink_exist() {
+                    Ok(())
+                } else {
+                    Err(e)
+                }
+            }
+        }
+    }
+
+    /// Index and name of links whose name starts with `prefix`.
+    pub async fn list_links(
+        &self,
+        prefix: &str,
+        netns: Option<&str>,
+    ) -> Result<Vec<(u32, String)>> {
+        let mut links = self
+            .handle(netns.unwrap_or(DEFAULT_HANDLE))
+            .link()
+            .get()
+            .execute();
+
+        let mut result = vec![];
+        while let Some(link) = links.try_next().await? {
+            for attr in &link.attributes {
+                if let LinkAttribute::IfName(name) = attr
+                    && name.starts_with(prefix)
+                {
+                    result.push((link.header.index, name.clone()));
+                }
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Poll until the link shows up or `timeout` elapses, e.g. after it was moved into another
+    /// netns. The last error is returned on timeout.
+    pub async fn wait_for_link(
+        &self,
+        name: &str,
+        netns: Option<&str>,
+        timeout: Duration,
+    ) -> Result<LinkMessage> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            match self.get_link(name, netns).await {
+                Ok(link) => return Ok(link),
+                Err(e) if Instant::now() >= deadline => return Err(e),
+                Err(_) => sleep(LINK_POLL_INTERVAL).await,
+            }
+        }
+    }
+
+    /// Whether the link is administratively up and operational (IFF_UP and IFF_RUNNING).
+    pub async fn is_link_up(&self, name: &str, netns: Option<&str>) -> Result<bool> {
+        let link = self.get_link(name, netns).await?;
+
+        Ok(link.header.flags.contains(LinkFlags::Up)
+            && link.header.flags.contains(LinkFlags::Running))
+    }
+
+    pub async fn get_addresses(&self, link: &str, netns: Option<&str>) -> Result<Vec<IpNetwork>> {
+        let index = self.get_link(link, netns).await?.header.index;
+        let mut addresses = self
+            .handle(netns.unwrap_or(DEFAULT_HANDLE))
+            .address()
+            .get()
+            .set_link_index_filter(index)
+            .execute();
+
+        let mut result = vec![];
+        while let Some(msg) = addresses.try_next().await? {
+            // IFA_LOCAL is the address of interface for point-to-point links, IFA_ADDRESS
+            // is the peer one
+            let mut ip = None;
+            for attr in &msg.attributes {
+                match attr {
+                    AddressAttribute::Local(local) => ip = Some(*local),
+                    AddressAttribute::Address(address) if ip.is_none() => ip = Some(*address),
+                    _ => {}
+                }
+            }
+
+            if let Some(ip) = ip {
+                result.push(IpNetwork {
+                    ip,
+                    mask: msg.header.prefix_len,
+                });
+            }
+        }
+
+        Ok(result)
+    }
+
     pub async fn create_vrf(
         &self,
         name: &str,
         table_id: u32,
         address: Vec<IpNetwork>,
     ) -> Result<LinkMessage> {
+        // a vrf of another table isn't reused, routes would go to a table it doesn't look up
+        if self.idempotent
+            && let Ok(link) = self.get_link(name, None).await
+            && vrf_table(&link) != Some(table_id)
+        {
+            self.delete_link(name, None).await?;
+        }
+        self.add_link(name, None, LinkVrf::new(name, table_id).up().build())
+            .await?;
+
+        let link = self.get_link(name, None).await?;
+
+        self.add_addresses(link.header.index, &address, None)
+            .await?;
+
+        Ok(link)
+    }
+
+    /// Probe VRF support of the kernel by creating a temporary vrf device, the kernel loads
+    /// the vrf module on demand.
+    pub async fn supports_vrf(&self) -> Result<bool> {
+        let r = self
+            .handle(DEFAULT_HANDLE)
+            .link()
+            .add(LinkVrf::new(VRF_PROBE_NAME, VRF_PROBE_TABLE).build())
+            .execute()
+            .await;
+
+        match r {
+            Ok(_) => {
+                self.delete_link(VRF_PROBE_NAME, None).await?;
+                Ok(true)
+            }
+            Err(e) => {
+                let e = NetlinkError::from(e);
+                if e.is_netlink_not_supported() {
+                    Ok(false)
+                } else if e.is_netlink_exist() {
+                    // left over from an interrupted probe
+                    self.delete_link(VRF_PROBE_NAME, None).await?;
+                    Ok(true)
+                } else {
+                    Err(e)
+                }
+            }
+        }
+    }
+
+    /// add addresses to the link at `index` with pipelined requests
+    async fn add_addresses(
+        &self,
+        index: u32,
+        addresses: &[IpNetwork],
+        netns: Option<&str>,
+    ) -> Result<()> {
+        let handle = self.handle(netns.unwrap_or(DEFAULT_HANDLE));
+        let idempotent = self.idempotent;
+        pipelined(addresses, |ip| {
+            let request = handle.address().add(index, ip.ip, ip.mask).execute();
+            async move {
+                match request.await {
+                    // the address is left on a reused link
+                    Err(e) if idempotent && NetlinkError::from(e.clone()).is_netlink_exist() => {
+                        Ok(())
+                    }
+                    r => r,
+                }
+            }
+        })
+        .await
+    }
+
+    pub async fn create_dummy(&self, name: &str, address: Vec<IpNetwork>) -> Result<LinkMessage> {
         self.handle(DEFAULT_HANDLE)
             .link()
-            .add(LinkVrf::new(name, table_id).up().build())
+            .add(LinkDummy::new(name).up().build())
             .execute()
             .await?;
 
         let link = self.get_link(name, None).await?;
 
-        for ip in address {
-            self.handle(DEFAULT_HANDLE)
-                .address()
-                .add(link.header.index, ip.ip, ip.mask)
-                .execute()
-                .await?;
-        }
+        self.add_addresses(link.header.index, &address, None)
+            .await?;
 
         Ok(link)
     }
@@ -180,6 +552,8 @@ impl Netlink {
         name: &str,
         id: u32,
         master: Option<&str>,
+        mtu: u32,
+        alias: Option<&str>,
         netns: Option<&str>,
     ) -> Result<()> {
         let mut master_index = 0;
@@ -190,7 +564,7 @@ impl Netlink {
 
         let mut xfrm_msg = LinkXfrm::new(name, 0, id)
             .controller(master_index)
-            .mtu(1400)
+            .mtu(mtu)
             .up();
 
         let ns_file: File;
@@ -204,9 +578,41 @@ impl Netlink {
         xfrm.header.flags |= LinkFlags::Multicast;
         xfrm.header.change_mask |= LinkFlags::Multicast;
 
-        self.handle(DEFAULT_HANDLE)
+        self.add_link(name, netns, xfrm).await?;
+
+        if let Some(alias) = alias {
+            // the link may not show up in the netns immediately
+            if netns.is_some() {
+                self.wait_for_link(name, netns, LINK_WAIT_TIMEOUT).await?;
+            }
+            self.set_link_alias(name, alias, netns).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Set IFLA_IFALIAS of the link, which is shown by `ip link`.
+    pub async fn set_link_alias(&self, name: &str, alias: &str, netns: Option<&str>) -> Result<()> {
+        let mut msg = LinkUnspec::new_with_name(name).build();
+        msg.attributes
+            .push(LinkAttribute::IfAlias(alias.to_string()));
+
+        self.handle(netns.unwrap_or(DEFAULT_HANDLE))
             .link()
-            .add(xfrm)
+            .set(msg)
+            .execute()
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn set_link_txqlen(&self, name: &str, len: u32, netns: Option<&str>) -> Result<()> {
+        let mut msg = LinkUnspec::new_with_name(name).build();
+        msg.attributes.push(LinkAttribute::TxQueueLen(len));
+
+        self.handle(netns.unwrap_or(DEFAULT_HANDLE))
+            .link()
+            .set(msg)
             .execute()
             .await?;
 
@@ -224,26 +630,28 @@ impl Netlink {
         Ok(())
     }
 
+    /// Create a route to `dest` through `output`.
     pub async fn create_route(
         &self,
         dest: IpNetwork,
         output: &str,
-        gateway: Option<IpAddr>,
-        table: Option<u32>,
-        priority: Option<u32>,
-        kind: Option<RouteType>,
-        scope: Option<RouteScope>,
-        netns: Option<&str>,
+        spec: RouteSpec<'_>,
     ) -> Result<()> {
-        let mut table_id = 254;
+        let RouteSpec {
+            gateway,
+            prefsrc,
+            table,
+            priority,
+            kind,
+            scope,
+            netns,
+        } = spec;
+        let table_id = table.unwrap_or(254);
         let handle: &Handle = self.handle(netns.unwrap_or(DEFAULT_HANDLE));
 
-        if table.is_some() {
-            table_id = table.unwrap();
-        }
-
         let mut route = RouteMessageBuilder::<IpAddr>::new()
             .table_id(table_id)
+            .protocol(ROUTE_PROTOCOL)
             .destination_prefix(dest.formatted_ip(), dest.mask)
             .map_err(|e| NetlinkError::new(&format!("{e}")))?;
 
@@ -259,13 +667,17 @@ impl Netlink {
             }
         }
 
+        if let Some(src) = prefsrc {
+            route = route
+                .pref_source(src)
+                .map_err(|e| NetlinkError::new(&format!("{e}")))?;
+        }
+
         if let Some(p) = priority {
             route = route.priority(p);
         }
 
-        if let Some(s) = scope {
-            route = route.scope(s);
-        }
+        route = route.scope(scope.unwrap_or(route_scope(gateway, kind)));
 
         let index = self.get_link(output, netns).await?;
         route = route.output_interface(index.header.index);
@@ -279,40 +691,157 @@ impl Netlink {
         Ok(())
     }
 
-    pub async fn create_rule(&self, priority: u32, table_id: u32) -> Result<()> {
-        self.handle(DEFAULT_HANDLE)
-            .rule()
-            .add()
-            .priority(priority)
-            .table_id(table_id)
-            .execute()
-            .await?;
+    /// lookup `table_id` for packets of `family`, only packets with `fw_mark` if it is set
+    pub async fn create_rule(
+        &self,
+        priority: u32,
+        table_id: u32,
+        fw_mark: Option<u32>,
+        family: AddressFamily,
+    ) -> Result<()> {
+        let rule = self.handle(DEFAULT_HANDLE).rule();
+
+        match family {
+            AddressFamily::Ip4 => {
+                let mut req = rule.add().v4().priority(priority).table_id(table_id);
+                if let Some(mark) = fw_mark {
+                    req = req.fw_mark(mark);
+                }
+                req.execute().await?;
+            }
+            AddressFamily::Ip6 => {
+                let mut req = rule.add().v6().priority(priority).table_id(table_id);
+                if let Some(mark) = fw_mark {
+                    req = req.fw_mark(mark);
+                }
+                req.execute().await?;
+            }
+        }
 
         Ok(())
     }
 
-    pub async fn create_netns(&mut self, name: &str) -> Result<()> {
+    /// delete rules of `family` looking up `table_id`, only the ones with `fw_mark` if it is set
+    pub async fn delete_rule(
+        &self,
+        table_id: u32,
+        fw_mark: Option<u32>,
+        family: AddressFamily,
+    ) -> Result<()> {
+        let handle = self.handle(DEFAULT_HANDLE);
+        let version = match family {
+            AddressFamily::Ip4 => IpVersion::V4,
+            AddressFamily::Ip6 => IpVersion::V6,
+        };
+        let mut rules = handle.rule().get(version).execute();
+
+        while let Some(rule) = rules.try_next().await? {
+            let marked = match fw_mark {
+                Some(mark) => rule.attributes.contains(&RuleAttribute::FwMark(mark)),
+                None => true,
+            };
+            if marked && rule.attributes.contains(&RuleAttribute::Table(table_id)) {
+                handle.rule().del(rule).execute().await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Create netns `name` or open the existing one, returns whether it was created.
+    pub async fn create_netns(&mut self, name: &str) -> Result<bool> {
         let r = fs::try_exists(format!("/var/run/netns/{name}")).await;
-        if r.is_err() || !r.unwrap() {
+        let created = r.is_err() || !r.unwrap();
+        if created {
             NetworkNamespace::add(name.to_string()).await?;
+        } else if self.handles.contains_key(name) {
+            // reuse the handle of existing netns, instead of leaking its connection
+            return Ok(false);
         }
 
         self.pushns(name)
             .map_err(|e| NetlinkError::new(&format!("{e}")))?;
         let (connection, handle, _) = new_connection().expect("cannot create netlink connection");
-        tokio::spawn(connection);
+        let task = tokio::spawn(connection);
         self.popns()
             .map_err(|e| NetlinkError::new(&format!("{e}")))?;
         self.handles.insert(name.to_string(), handle);
+        if let Some(old) = self.connections.insert(name.to_string(), task) {
+            old.abort();
+        }
+        Ok(created)
+    }
+
+    /// Bind-mount `resolv_conf` to `/etc/netns/<name>/resolv.conf`, which `ip netns exec` mounts
+    /// over `/etc/resolv.conf` of processes in the netns. It is unmounted by `delete_netns`, a
+    /// mount left by a previous run is replaced instead of stacked on.
+    pub async fn mount_resolv_conf(&mut self, name: &str, resolv_conf: &Path) -> Result<()> {
+        let target = netns_resolv_conf(name);
+        let err = |e: &dyn Display| {
+            NetlinkError::new(&format!(
+                "failed to mount {resolv_conf:?} to {target:?}: {e}"
+            ))
+        };
+
+        if self.resolv_confs.contains_key(name) {
+            return Ok(());
+        }
+        fs::create_dir_all(target.parent().unwrap())
+            .await
+            .map_err(|e| err(&e))?;
+        let created = !fs::try_exists(&target).await.unwrap_or(false);
+        if created {
+            File::create(&target).await.map_err(|e| err(&e))?;
+        }
+        while is_mounted(&target).await {
+            umount(&target).map_err(|e| err(&e))?;
+        }
+
+        mount(
+            Some(resolv_conf),
+            &target,
+            None::<&str>,
+            MsFlags::MS_BIND,
+            None::<&str>,
+        )
+        .map_err(|e| err(&e))?;
+        self.resolv_confs.insert(name.to_string(), created);
+
+        Ok(())
+    }
+
+    /// Undo `mount_resolv_conf`, also mounts of previous runs which aren't tracked. The mount
+    /// point is only removed if it was created for it.
+    async fn unmount_resolv_conf(&mut self, name: &str) -> Result<()> {
+        let created = self.resolv_confs.remove(name).unwrap_or(false);
+        let target = netns_resolv_conf(name);
+
+        while is_mounted(&target).await {
+            umount(&target)
+                .map_err(|e| NetlinkError::new(&format!("failed to unmount {target:?}: {e}")))?;
+        }
+        if created {
+            let _ = fs::remove_file(&target).await;
+            // only succeeds if nothing else is in it
+            let _ = fs::remove_dir(target.parent().unwrap()).await;
+        }
+
         Ok(())
     }
 
     pub async fn delete_netns(&mut self, name: &str) -> Result<()> {
         let r = fs::try_exists(format!("/var/run/netns/{name}")).await;
 
+        if let Err(e) = self.unmount_resolv_conf(name).await {
+            warn!("{e}");
+        }
+
         if r.is_ok() && r.unwrap() {
             NetworkNamespace::del(name.to_string()).await?;
             self.handles.remove(name);
+            if let Some(task) = self.connections.remove(name) {
+                task.abort();
+            }
             Ok(())
         } else {
             Ok(())
@@ -336,21 +865,12 @@ impl Netlink {
             veth_msg = veth_msg.setns_by_fd(ns_file.as_raw_fd());
         }
 
-        self.handle(DEFAULT_HANDLE)
-            .link()
-            .add(veth_msg.build())
-            .execute()
-            .await?;
+        // deleting either end of a veth pair deletes both
+        self.add_link(name, None, veth_msg.build()).await?;
 
         if let Some(addrs) = address {
             let link = self.get_link(name, None).await?;
-            for ip in addrs {
-                self.handle(DEFAULT_HANDLE)
-                    .address()
-                    .add(link.header.index, ip.ip, ip.mask)
-                    .execute()
-                    .await?;
-            }
+            self.add_addresses(link.header.index, addrs, None).await?;
         }
         self.handle(DEFAULT_HANDLE)
             .link()
@@ -361,13 +881,8 @@ impl Netlink {
         // configure peer interface
         if let Some(peer_addrs) = peer_address {
             let link = self.get_link(peer_name, peer_netns).await?;
-            for ip in peer_addrs {
-                self.handle(peer_netns.unwrap_or(DEFAULT_HANDLE))
-                    .address()
-                    .add(link.header.index, ip.ip, ip.mask)
-                    .execute()
-                    .await?;
-            }
+            self.add_addresses(link.header.index, peer_addrs, peer_netns)
+                .await?;
         }
         self.handle(peer_netns.unwrap_or(DEFAULT_HANDLE))
             .link()
@@ -378,6 +893,156 @@ impl Netlink {
         Ok(())
     }
 
+    /// Routes in `table`, including the ones created by kernel.
+    pub async fn list_routes(&self, table: u32, netns: Option<&str>) -> Result<Vec<RouteInfo>> {
+        let mut routes = self
+            .handle(netns.unwrap_or(DEFAULT_HANDLE))
+            .route()
+            .get(RouteMessageBuilder::<IpAddr>::new().build())
+            .execute();
+
+        let mut result = vec![];
+        while let Some(route) = routes.try_next().await? {
+            // default routes have no destination
+            let mut dest = match route.header.address_family {
+                NlAddressFamily::Inet6 => IpAddr::from(Ipv6Addr::UNSPECIFIED),
+                _ => IpAddr::from(Ipv4Addr::UNSPECIFIED),
+            };
+            let mut gateway = None;
+            let mut oif = None;
+            for attr in &route.attributes {
+                match attr {
+                    RouteAttribute::Destination(RouteAddress::Inet(ip)) => dest = (*ip).into(),
+                    RouteAttribute::Destination(RouteAddress::Inet6(ip)) => dest = (*ip).into(),
+                    RouteAttribute::Gateway(RouteAddress::Inet(ip)) => gateway = Some((*ip).into()),
+                    RouteAttribute::Gateway(RouteAddress::Inet6(ip)) => {
+                        gateway = Some((*ip).into())
+                    }
+                    // ipv4 routes with ipv6 gateway
+                    RouteAttribute::Via(RouteVia::Inet6(ip)) => gateway = Some((*ip).into()),
+                    RouteAttribute::Oif(id) => oif = Some(*id),
+                    _ => {}
+                }
+            }
+
+            if route_table(&route) != table {
+                continue;
+            }
+            result.push(RouteInfo {
+                dest: IpNetwork {
+                    ip: dest,
+                    mask: route.header.destination_prefix_length,
+                },
+                gateway,
+                oif,
+                protocol: route.header.protocol,
+            });
+        }
+
+        Ok(result)
+    }
+
+    /// Copy routes of `protocol` in table `from` into table `to`, routes already in `to` are
+    /// kept. Returns the number of copied routes.
+    pub async fn copy_routes(
+        &self,
+        from: u32,
+        to: u32,
+        protocol: RouteProtocol,
+        netns: Option<&str>,
+    ) -> Result<usize> {
+        let handle = self.handle(netns.unwrap_or(DEFAULT_HANDLE));
+        let mut routes = handle
+            .route()
+            .get(RouteMessageBuilder::<IpAddr>::new().build())
+            .execute();
+
+        let mut copied = 0;
+        while let Some(mut route) = routes.try_next().await? {
+            if route_table(&route) != from || route.header.protocol != protocol {
+                continue;
+            }
+
+            set_route_table(&mut route, to);
+            match handle.route().add(route).execute().await {
+                Ok(_) => copied += 1,
+                Err(e) => {
+                    let e = NetlinkError::from(e);
+                    if !e.is_netlink_exist() {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+
+        Ok(copied)
+    }
+
+    /// Delete routes of `protocol` in `table`, routes of others are left untouched.
+    pub async fn flush_routes(
+        &self,
+        table: u32,
+        protocol: RouteProtocol,
+        netns: Option<&str>,
+    ) -> Result<()> {
+        let handle = self.handle(netns.unwrap_or(DEFAULT_HANDLE));
+        let mut routes = handle
+            .route()
+            .get(RouteMessageBuilder::<IpAddr>::new().build())
+            .execute();
+
+        while let Some(route) = routes.try_next().await? {
+            if route_table(&route) == table && route.header.protocol == protocol {
+                handle.route().del(route).execute().await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Destinations of routes through `output` in `table`, which were created by
+    /// `create_route` rather than by kernel.
+    pub async fn get_routes(
+        &self,
+        output: &str,
+        table: u32,
+        netns: Option<&str>,
+    ) -> Result<Vec<IpNetwork>> {
+        let index = self.get_link(output, netns).await?.header.index;
+
+        Ok(self
+            .list_routes(table, netns)
+            .await?
+            .into_iter()
+            .filter(|route| route.protocol == ROUTE_PROTOCOL && route.oif == Some(index))
+            .map(|route| route.dest)
+            .collect())
+    }
+
+    pub async fn delete_route(
+        &self,
+        dest: IpNetwork,
+        output: &str,
+        table: u32,
+        netns: Option<&str>,
+    ) -> Result<()> {
+        let index = self.get_link(output, netns).await?.header.index;
+        let route = RouteMessageBuilder::<IpAddr>::new()
+            .table_id(table)
+            .destination_prefix(dest.formatted_ip(), dest.mask)
+            .map_err(|e| NetlinkError::new(&format!("{e}")))?
+            .output_interface(index)
+            .build();
+
+        self.handle(netns.unwrap_or(DEFAULT_HANDLE))
+            .route()
+            .del(route)
+            .execute()
+            .await?;
+
+        Ok(())
+    }
+
     pub async fn delete_route_from_outdev(&self, name: &str) -> Result<()> {
         let iface = self.get_link(name, None).await?;
         let mut routes = self
@@ -408,9 +1073,14 @@ impl Netlink {
         Ok(())
     }
 
-    pub async fn move_link_to_netns(&self, name: &str, netns: &str) -> Result<()> {
+    pub async fn move_link_to_netns(
+        &self,
+        name: &str,
+        from: Option<&str>,
+        netns: &str,
+    ) -> Result<()> {
         let netns_file = File::open(format!("/var/run/netns/{netns}")).await?;
-        self.handle(DEFAULT_HANDLE)
+        self.handle(from.unwrap_or(DEFAULT_HANDLE))
             .link()
             .set(
                 LinkUnspec::new_with_name(name)
@@ -423,6 +1093,27 @@ impl Netlink {
 
         Ok(())
     }
+
+    /// Move a link from `from_netns` back to the netns of the caller, some kernels only delete
+    /// interfaces cleanly in the netns they were created in.
+    pub async fn move_link_to_root(&self, name: &str, from_netns: &str) -> Result<()> {
+        // the netns before `pushns` if the caller switched
+        let root_file = match self.netns_stack.first() {
+            Some(f) => f.try_clone()?,
+            None => std::fs::File::open("/proc/self/ns/net")?,
+        };
+        self.handle(from_netns)
+            .link()
+            .set(
+                LinkUnspec::new_with_name(name)
+                    .setns_by_fd(root_file.as_raw_fd())
+                    .build(),
+            )
+            .execute()
+            .await?;
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -442,7 +1133,9 @@ mod test {
             r.err().unwrap()
         );
 
-        let r = nl.create_xfrm("test", 1, None, Some("aronet-test")).await;
+        let r = nl
+            .create_xfrm("test", 1, None, 1400, None, Some("aronet-test"))
+            .await;
         assert!(
             r.is_ok(),
             "failed to create interface \"test\": {}",
@@ -480,14 +1173,12 @@ mod test {
                 "aronet-test",
                 "aronet-test",
                 Some("aronet-test"),
-                Some(&vec![IpNetwork {
-                    ip: IpAddr::from_str("127.1.1.1").unwrap(),
-                    mask: 32,
-                }]),
-                Some(&vec![IpNetwork {
-                    ip: IpAddr::from_str("127.1.1.2").unwrap(),
-                    mask: 32,
-                }]),
+                Some(&vec![IpNetwork::from(
+                    IpAddr::from_str("127.1.1.1").unwrap(),
+                )]),
+                Some(&vec![IpNetwork::from(
+                    IpAddr::from_str("127.1.1.2").unwrap(),
+                )]),
             )
             .await;
         assert!(
@@ -503,4 +1194,598 @@ mod test {
             r.err().unwrap()
         );
     }
+
+    #[tokio::test]
+    #[ignore = "requires CAP_NET_ADMIN"]
+    async fn create_links_idempotent() {
+        let mut nl = Netlink::new().await;
+        nl.set_idempotent(true);
+        let address = vec![IpNetwork::from_str("fd00:4::1/64").unwrap()];
+
+        let vrf = nl
+            .create_vrf("aronet-idem", 4242, address.clone())
+            .await
+            .unwrap();
+        let again = nl
+            .create_vrf("aronet-idem", 4242, address.clone())
+            .await
+            .unwrap();
+        assert_eq!(vrf.header.index, again.header.index);
+        // but not with another table
+        let other = nl
+            .create_vrf("aronet-idem", 4243, address.clone())
+            .await
+            .unwrap();
+        assert_ne!(vrf.header.index, other.header.index);
+        assert_eq!(vrf_table(&other), Some(4243));
+        let vrf = other;
+
+        nl.create_xfrm("aronet-idemx", 4242, None, 1400, Some("a"), None)
+            .await
+            .unwrap();
+        let xfrm = nl.get_link("aronet-idemx", None).await.unwrap();
+        nl.create_xfrm("aronet-idemx", 4242, None, 1400, Some("a"), None)
+            .await
+            .unwrap();
+        let again = nl.get_link("aronet-idemx", None).await.unwrap();
+        assert_eq!(xfrm.header.index, again.header.index);
+
+        nl.create_veth("aronet-idemv", "aronet-idemp", None, Some(&address), None)
+            .await
+            .unwrap();
+        let veth = nl.get_link("aronet-idemv", None).await.unwrap();
+        nl.create_veth("aronet-idemv", "aronet-idemp", None, Some(&address), None)
+            .await
+            .unwrap();
+        let again = nl.get_link("aronet-idemv", None).await.unwrap();
+        assert_eq!(veth.header.index, again.header.index);
+
+        // the existing link is replaced if not idempotent
+        nl.set_idempotent(false);
+        let replaced = nl
+            .create_vrf("aronet-idem", 4242, address.clone())
+            .await
+            .unwrap();
+        assert_ne!(vrf.header.index, replaced.header.index);
+
+        for name in ["aronet-idem", "aronet-idemx", "aronet-idemv"] {
+            nl.delete_link(name, None).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "requires CAP_NET_ADMIN"]
+    async fn move_link_to_root() {
+        let mut nl = Netlink::new().await;
+
+        nl.create_netns("aronet-test-move").await.unwrap();
+        nl.create_xfrm("aronet-move", 4243, None, 1400, None, None)
+            .await
+            .unwrap();
+        nl.move_link_to_netns("aronet-move", None, "aronet-test-move")
+            .await
+            .unwrap();
+        nl.wait_for_link(
+            "aronet-move",
+            Some("aronet-test-move"),
+            Duration::from_secs(1),
+        )
+        .await
+        .unwrap();
+        assert!(nl.get_link("aronet-move", None).await.is_err());
+
+        nl.move_link_to_root("aronet-move", "aronet-test-move")
+            .await
+            .unwrap();
+        nl.wait_for_link("aronet-move", None, Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert!(
+            nl.get_link("aronet-move", Some("aronet-test-move"))
+                .await
+                .is_err()
+        );
+
+        nl.delete_link("aronet-move", None).await.unwrap();
+        nl.delete_netns("aronet-test-move").await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "requires CAP_NET_ADMIN"]
+    async fn create_netns_twice() {
+        let mut nl = Netlink::new().await;
+
+        assert!(nl.create_netns("aronet-test-twice").await.unwrap());
+        assert!(!nl.create_netns("aronet-test-twice").await.unwrap());
+
+        assert_eq!(nl.handles.len(), 2);
+        assert_eq!(nl.connections.len(), 2);
+        assert!(nl.connections.values().all(|task| !task.is_finished()));
+
+        nl.delete_netns("aronet-test-twice").await.unwrap();
+        assert_eq!(nl.handles.len(), 1);
+        assert_eq!(nl.connections.len(), 1);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires CAP_NET_ADMIN"]
+    async fn mount_resolv_conf() {
+        let mut nl = Netlink::new().await;
+        let source = std::env::temp_dir().join(format!("aronet-resolv-{}", std::process::id()));
+        std::fs::write(&source, "nameserver 10.0.0.53\n").unwrap();
+        let target = netns_resolv_conf("aronet-test-resolv");
+
+        nl.create_netns("aronet-test-resolv").await.unwrap();
+        nl.mount_resolv_conf("aronet-test-resolv", &source)
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&target).unwrap(),
+            "nameserver 10.0.0.53\n"
+        );
+
+        // a restarted daemon doesn't know about the mount, it is replaced instead of stacked on
+        let mut restarted = Netlink::new().await;
+        restarted
+            .mount_resolv_conf("aronet-test-resolv", &source)
+            .await
+            .unwrap();
+        let mountinfo = std::fs::read_to_string("/proc/self/mountinfo").unwrap();
+        let target_str = target.to_string_lossy();
+        assert_eq!(
+            mountinfo
+                .lines()
+                .filter(|line| line.split(' ').nth(4) == Some(&target_str))
+                .count(),
+            1
+        );
+
+        nl.delete_netns("aronet-test-resolv").await.unwrap();
+        assert!(!is_mounted(&target).await);
+        std::fs::remove_file(&source).unwrap();
+        assert!(!target.exists());
+        assert!(!target.parent().unwrap().exists());
+    }
+
+    #[test]
+    fn test_is_mount_point() {
+        let mountinfo = "\
+22 1 0:21 / /proc rw,nosuid - proc proc rw
+97 28 0:5 /run/aronet/resolv.conf /etc/netns/aronet/resolv.conf rw - tmpfs tmpfs rw
+98 28 0:5 / /etc/netns/with\\040space/resolv.conf rw - tmpfs tmpfs rw
+";
+        assert!(is_mount_point(
+            mountinfo,
+            Path::new("/etc/netns/aronet/resolv.conf")
+        ));
+        assert!(is_mount_point(
+            mountinfo,
+            Path::new("/etc/netns/with space/resolv.conf")
+        ));
+        // the root of a mount is in the 4th field, not a mount point
+        assert!(!is_mount_point(
+            mountinfo,
+            Path::new("/run/aronet/resolv.conf")
+        ));
+        assert!(!is_mount_point(mountinfo, Path::new("/etc/netns/other")));
+    }
+
+    #[tokio::test]
+    #[ignore = "requires CAP_NET_ADMIN"]
+    async fn create_route_with_prefsrc() {
+        let mut nl = Netlink::new().await;
+        let src = IpAddr::from_str("fd00:2::1").unwrap();
+
+        nl.create_netns("aronet-test-src").await.unwrap();
+        nl.create_veth(
+            "aronet-src",
+            "aronet-src",
+            Some("aronet-test-src"),
+            None,
+            Some(&vec![IpNetwork::from(src)]),
+        )
+        .await
+        .unwrap();
+
+        let r = nl
+            .create_route(
+                IpNetwork::from_str("fd00:3::/64").unwrap(),
+                "aronet-src",
+                RouteSpec {
+                    prefsrc: Some(src),
+                    netns: Some("aronet-test-src"),
+                    ..Default::default()
+                },
+            )
+            .await;
+        assert!(r.is_ok(), "failed to create route: {}", r.err().unwrap());
+
+        let mut routes = nl
+            .handle("aronet-test-src")
+            .route()
+            .get(RouteMessageBuilder::<IpAddr>::new().build())
+            .execute();
+        let mut found = false;
+        while let Some(route) = routes.try_next().await.unwrap() {
+            found |= route
+                .attributes
+                .contains(&RouteAttribute::PrefSource(RouteAddress::from(src)));
+        }
+        assert!(found, "route with PREFSRC {src} not found");
+
+        nl.delete_netns("aronet-test-src").await.unwrap();
+    }
+
+    #[test]
+    fn test_vrf_table() {
+        let mut link = LinkMessage::default();
+        assert_eq!(vrf_table(&link), None);
+
+        link.attributes
+            .push(LinkAttribute::LinkInfo(vec![LinkInfo::Data(
+                InfoData::Vrf(vec![InfoVrf::TableId(128)]),
+            )]));
+        assert_eq!(vrf_table(&link), Some(128));
+    }
+
+    #[test]
+    fn test_route_scope() {
+        let gateway = Some(IpAddr::from_str("fd00::1").unwrap());
+        assert_eq!(route_scope(None, None), RouteScope::Link);
+        assert_eq!(route_scope(gateway, None), RouteScope::Universe);
+        assert_eq!(
+            route_scope(gateway, Some(RouteType::Unicast)),
+            RouteScope::Universe
+        );
+        assert_eq!(route_scope(None, Some(RouteType::Local)), RouteScope::Host);
+        assert_eq!(
+            route_scope(None, Some(RouteType::Unreachable)),
+            RouteScope::Universe
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "requires CAP_NET_ADMIN"]
+    async fn create_route_scope() {
+        let mut nl = Netlink::new().await;
+        let address = IpNetwork::from_str("fd00:5::1/64").unwrap();
+
+        nl.create_netns("aronet-test-scope").await.unwrap();
+        nl.create_veth(
+            "aronet-scope",
+            "aronet-scope",
+            Some("aronet-test-scope"),
+            None,
+            Some(&vec![address]),
+        )
+        .await
+        .unwrap();
+
+        let routes = [
+            ("fd00:6::/64", None),
+            ("fd00:7::/64", Some(IpAddr::from_str("fd00:5::2").unwrap())),
+        ];
+        for (dest, gateway) in routes {
+            nl.create_route(
+                IpNetwork::from_str(dest).unwrap(),
+                "aronet-scope",
+                RouteSpec {
+                    gateway,
+                    netns: Some("aronet-test-scope"),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        }
+
+        let mut scopes = HashMap::new();
+        let mut routes = nl
+            .handle("aronet-test-scope")
+            .route()
+            .get(RouteMessageBuilder::<IpAddr>::new().build())
+            .execute();
+        while let Some(route) = routes.try_next().await.unwrap() {
+            for attr in &route.attributes {
+                if let RouteAttribute::Destination(RouteAddress::Inet6(ip)) = attr {
+                    scopes.insert(ip.to_string(), route.header.scope);
+                }
+            }
+        }
+        nl.delete_netns("aronet-test-scope").await.unwrap();
+
+        assert_eq!(scopes["fd00:6::"], RouteScope::Link);
+        assert_eq!(scopes["fd00:7::"], RouteScope::Universe);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires CAP_NET_ADMIN"]
+    async fn create_fwmark_rule() {
+        let nl = Netlink::new().await;
+        let (mark, table) = (0x1234, 4242);
+
+        let r = nl
+            .create_rule(30000, table, Some(mark), AddressFamily::Ip6)
+            .await;
+        assert!(r.is_ok(), "failed to create rule: {}", r.err().unwrap());
+
+        let mut rules = nl
+            .handle(DEFAULT_HANDLE)
+            .rule()
+            .get(IpVersion::V6)
+            .execute();
+        let mut created = None;
+        while let Some(rule) = rules.try_next().await.unwrap() {
+            if rule.attributes.contains(&RuleAttribute::FwMark(mark))
+                && rule.attributes.contains(&RuleAttribute::Table(table))
+            {
+                created = Some(rule);
+            }
+        }
+        let rule = created.expect("rule with fwmark not found");
+
+        nl.handle(DEFAULT_HANDLE)
+            .rule()
+            .del(rule)
+            .execute()
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "requires CAP_NET_ADMIN"]
+    async fn wait_for_link() {
+        let nl = Netlink::new().await;
+
+        let create = async {
+            sleep(Duration::from_millis(100)).await;
+            nl.create_dummy("aronet-wait", vec![]).await
+        };
+        let (waited, created) = tokio::join!(
+            nl.wait_for_link("aronet-wait", None, Duration::from_secs(2)),
+            create
+        );
+        assert!(
+            created.is_ok(),
+            "failed to create link: {}",
+            created.err().unwrap()
+        );
+        assert_eq!(waited.unwrap().header.index, created.unwrap().header.index);
+        nl.delete_link("aronet-wait", None).await.unwrap();
+
+        let r = nl
+            .wait_for_link("aronet-never", None, Duration::from_millis(50))
+            .await;
+        assert!(r.is_err());
+    }
+
+    #[tokio::test]
+    #[ignore = "requires CAP_NET_ADMIN"]
+    async fn create_dummy() {
+        let nl = Netlink::new().await;
+
+        let r = nl
+            .create_dummy(
+                "aronet-dummy",
+                vec![IpNetwork {
+                    ip: IpAddr::from_str("fd00:1::1").unwrap(),
+                    mask: 64,
+                }],
+            )
+            .await;
+        assert!(
+            r.is_ok(),
+            "failed to create dummy \"aronet-dummy\": {}",
+            r.err().unwrap()
+        );
+        assert!(nl.get_link("aronet-dummy", None).await.is_ok());
+
+        let r = nl.delete_link("aronet-dummy", None).await;
+        assert!(
+            r.is_ok(),
+            "failed to delete dummy \"aronet-dummy\": {}",
+            r.err().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "requires CAP_NET_ADMIN"]
+    async fn get_addresses() {
+        let nl = Netlink::new().await;
+        let addresses = vec![
+            IpNetwork::from_str("fd00:4::1/64").unwrap(),
+            IpNetwork::from_str("10.0.4.1/24").unwrap(),
+        ];
+
+        nl.create_dummy("aronet-addrs", addresses.clone())
+            .await
+            .unwrap();
+        let r = nl.get_addresses("aronet-addrs", None).await;
+        nl.delete_link("aronet-addrs", None).await.unwrap();
+
+        let r = r.unwrap();
+        for address in addresses {
+            assert!(r.contains(&address), "{address} not found in {r:?}");
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "requires CAP_NET_ADMIN"]
+    async fn set_link_alias() {
+        let nl = Netlink::new().await;
+        let alias = "aronet:O=example,CN=remote,serialNumber=0";
+
+        nl.create_xfrm("aronet-alias", 42, None, 1400, Some(alias), None)
+            .await
+            .unwrap();
+        let link = nl.get_link("aronet-alias", None).await;
+        nl.delete_link("aronet-alias", None).await.unwrap();
+
+        assert!(
+            link.unwrap()
+                .attributes
+                .contains(&LinkAttribute::IfAlias(alias.to_string()))
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "requires CAP_NET_ADMIN"]
+    async fn set_link_txqlen() {
+        let nl = Netlink::new().await;
+
+        nl.create_xfrm("aronet-txqlen", 43, None, 1400, None, None)
+            .await
+            .unwrap();
+        let r = nl.set_link_txqlen("aronet-txqlen", 4321, None).await;
+        let link = nl.get_link("aronet-txqlen", None).await;
+        nl.delete_link("aronet-txqlen", None).await.unwrap();
+
+        assert!(r.is_ok(), "failed to set txqueuelen: {}", r.err().unwrap());
+        assert!(
+            link.unwrap()
+                .attributes
+                .contains(&LinkAttribute::TxQueueLen(4321))
+        );
+    }
+
+    #[tokio::test]
+    async fn pipelined_requests() {
+        use std::cell::{Cell, RefCell};
+
+        let in_flight = Cell::new(0);
+        let max_in_flight = Cell::new(0);
+        let added = RefCell::new(vec![]);
+        let addresses: Vec<IpNetwork> = ["fd00::1/64", "fd00::2/64", "10.0.0.1/24"]
+            .iter()
+            .map(|n| IpNetwork::from_str(n).unwrap())
+            .collect();
+
+        let r = pipelined(&addresses, |ip| {
+            let (in_flight, max_in_flight, added) = (&in_flight, &max_in_flight, &added);
+            async move {
+                in_flight.set(in_flight.get() + 1);
+                max_in_flight.set(max_in_flight.get().max(in_flight.get()));
+                // wait for the response
+                tokio::task::yield_now().await;
+                added.borrow_mut().push(*ip);
+                in_flight.set(in_flight.get() - 1);
+                Ok(())
+            }
+        })
+        .await;
+
+        assert!(r.is_ok());
+        assert_eq!(*added.borrow(), addresses);
+        // all requests were sent before the first response, in a single round-trip
+        assert_eq!(max_in_flight.get(), addresses.len());
+    }
+
+    #[tokio::test]
+    #[ignore = "requires CAP_NET_ADMIN"]
+    async fn list_routes() {
+        let mut nl = Netlink::new().await;
+        let ns = Some("aronet-test-list");
+        let gateway = IpAddr::from_str("fd00:4::2").unwrap();
+
+        nl.create_netns("aronet-test-list").await.unwrap();
+        nl.create_veth(
+            "aronet-list",
+            "aronet-list",
+            ns,
+            None,
+            Some(&vec![IpNetwork::from_str("fd00:4::1/64").unwrap()]),
+        )
+        .await
+        .unwrap();
+        let index = nl.get_link("aronet-list", ns).await.unwrap().header.index;
+
+        for (dest, gateway) in [("fd00:5::/64", None), ("fd00:6::/48", Some(gateway))] {
+            nl.create_route(
+                IpNetwork::from_str(dest).unwrap(),
+                "aronet-list",
+                RouteSpec {
+                    gateway,
+                    table: Some(200),
+                    netns: ns,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        }
+
+        let routes = nl.list_routes(200, ns).await;
+        let main = nl.list_routes(254, ns).await;
+        nl.delete_netns("aronet-test-list").await.unwrap();
+
+        let mut routes = routes.unwrap();
+        routes.sort_by_key(|r| r.dest);
+        assert_eq!(
+            routes,
+            vec![
+                RouteInfo {
+                    dest: IpNetwork::from_str("fd00:5::/64").unwrap(),
+                    gateway: None,
+                    oif: Some(index),
+                    protocol: ROUTE_PROTOCOL,
+                },
+                RouteInfo {
+                    dest: IpNetwork::from_str("fd00:6::/48").unwrap(),
+                    gateway: Some(gateway),
+                    oif: Some(index),
+                    protocol: ROUTE_PROTOCOL,
+                },
+            ]
+        );
+        // routes of other tables are not listed
+        assert!(
+            main.unwrap()
+                .iter()
+                .all(|r| r.dest.to_string() != "fd00:5::/64")
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "requires CAP_NET_ADMIN"]
+    async fn supports_vrf() {
+        let nl = Netlink::new().await;
+
+        // kernels of common distributions ship the vrf module
+        assert!(nl.supports_vrf().await.unwrap());
+        // the probe device is removed, so probing again doesn't fail
+        assert!(nl.supports_vrf().await.unwrap());
+        assert!(nl.get_link(VRF_PROBE_NAME, None).await.is_err());
+    }
+
+    #[tokio::test]
+    #[ignore = "requires CAP_NET_ADMIN"]
+    async fn link_up_state() {
+        let mut nl = Netlink::new().await;
+
+        nl.create_netns("aronet-test-up").await.unwrap();
+        nl.create_veth("aronet-up", "aronet-up", Some("aronet-test-up"), None, None)
+            .await
+            .unwrap();
+
+        let r = nl.is_link_up("aronet-up", None).await;
+        assert!(r.unwrap(), "veth should be up after creation");
+        let r = nl.is_link_up("aronet-up", Some("aronet-test-up")).await;
+        assert!(r.unwrap(), "veth peer should be up after creation");
+
+        nl.handle(DEFAULT_HANDLE)
+            .link()
+            .set(LinkUnspec::new_with_name("aronet-up").down().build())
+            .execute()
+            .await
+            .unwrap();
+        let r = nl.is_link_up("aronet-up", None).await;
+        assert!(!r.unwrap(), "veth should be down");
+
+        let r = nl.delete_netns("aronet-test-up").await;
+        assert!(
+            r.is_ok(),
+            "failed to delete netns \"aronet-test-up\": {}",
+            r.err().unwrap()
+        );
+    }
 }
@@ -1,30 +1,47 @@
 use std::{
     collections::HashMap,
     ops::{Deref, DerefMut},
-    path::Path,
+    path::{Path, PathBuf},
+    time::Duration,
 };
 
 use futures::stream::TryStreamExt;
-use futures::{io, pin_mut};
-use serde::{Deserialize, Serialize, de::Visitor};
+use futures::{Stream, io, pin_mut};
+use log::debug;
+use serde::{Deserialize, Serialize, de::DeserializeOwned, de::IgnoredAny, de::Visitor};
+use tokio::net::{TcpStream, ToSocketAddrs};
 
-pub struct Client(rsvici::Client);
+use super::configuration::{ChildMode, DpdAction, Fragmentation, HwOffload, Revocation};
+
+pub struct Client {
+    inner: rsvici::Client,
+    /// one-shot requests fail after this, subscriptions never time out
+    request_timeout: Option<Duration>,
+}
 
 impl Deref for Client {
     type Target = rsvici::Client;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.inner
     }
 }
 
 impl DerefMut for Client {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+        &mut self.inner
     }
 }
 
-#[derive(Debug, Deserialize)]
+/// timeouts of vici, nothing times out if not set
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ViciTimeouts {
+    pub connect: Option<Duration>,
+    /// one-shot requests, subscriptions are long-lived and exempt
+    pub request: Option<Duration>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Version {
     daemon: String,
     version: String,
@@ -33,6 +50,33 @@ pub struct Version {
     machine: String,
 }
 
+/// subset of the `stats` response, numbers are strings as in vici
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Stats {
+    pub uptime: Uptime,
+    pub workers: Workers,
+    pub ikesas: IkeSaStats,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Uptime {
+    pub running: String,
+    pub since: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Workers {
+    pub total: String,
+    pub idle: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct IkeSaStats {
+    pub total: String,
+    pub half_open: String,
+}
+
 #[derive(Debug)]
 pub struct Updown {
     pub up: Option<bool>,
@@ -72,8 +116,13 @@ impl<'de> Visitor<'de> for UpdownVisitor {
             if key == "up" {
                 up = Some(map.next_value::<bool>()?);
             } else {
-                let sa = map.next_value::<IkeSa>()?;
-                ike_sas.insert(key, sa);
+                // keys which aren't IKE_SAs, e.g. added by newer charon, are ignored
+                match map.next_value::<UpdownValue>()? {
+                    UpdownValue::IkeSa(sa) => {
+                        ike_sas.insert(key, sa);
+                    }
+                    UpdownValue::Other(_) => debug!("ignore key {key} in updown event"),
+                }
             }
         }
 
@@ -81,7 +130,15 @@ impl<'de> Visitor<'de> for UpdownVisitor {
     }
 }
 
-#[derive(Debug, Deserialize)]
+/// value of an updown event key, anything but an IKE_SA is skipped
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum UpdownValue {
+    IkeSa(IkeSa),
+    Other(IgnoredAny),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct IkeSa {
     pub if_id_in: String,
@@ -90,6 +147,44 @@ pub struct IkeSa {
     pub remote_id: String,
 }
 
+/// old and new SA of a rekey event
+#[derive(Debug, Deserialize)]
+pub struct Rekey<T> {
+    pub old: T,
+    pub new: T,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RekeyedIkeSa {
+    pub uniqueid: String,
+    pub local_id: String,
+    pub remote_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RekeyedChildSa {
+    pub uniqueid: String,
+    pub spi_in: String,
+    pub spi_out: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ChildRekeyIkeSa {
+    pub uniqueid: String,
+    pub local_id: String,
+    pub remote_id: String,
+    pub child_sas: HashMap<String, Rekey<RekeyedChildSa>>,
+}
+
+/// `ike-rekey` event, keyed by connection name
+pub type IkeRekey = HashMap<String, Rekey<RekeyedIkeSa>>;
+
+/// `child-rekey` event, keyed by connection name
+pub type ChildRekey = HashMap<String, ChildRekeyIkeSa>;
+
 #[derive(Deserialize, Debug)]
 struct CommonResponse {
     success: bool,
@@ -97,15 +192,85 @@ struct CommonResponse {
 }
 
 impl CommonResponse {
-    pub fn ok_or(&self) -> io::Result<()> {
+    /// the failure as `ViciError` of `cmd`
+    pub fn ok_or(&self, cmd: &str) -> io::Result<()> {
         if self.success {
             Ok(())
         } else {
-            Err(io::Error::new(
-                std::io::ErrorKind::Other,
-                self.errmsg.clone().unwrap_or(String::from("")),
-            ))
+            Err(ViciError {
+                command: cmd.to_string(),
+                errmsg: self.errmsg.clone().unwrap_or_default(),
+                success: self.success,
+            }
+            .into())
+        }
+    }
+}
+
+/// A command which charon responded to with failure, the inner error of the `io::Error`
+/// returned by methods of `Client`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ViciError {
+    pub command: String,
+    pub errmsg: String,
+    pub success: bool,
+}
+
+impl ViciError {
+    pub fn from_io(e: &io::Error) -> Option<&ViciError> {
+        e.get_ref().and_then(|e| e.downcast_ref())
+    }
+}
+
+impl std::fmt::Display for ViciError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "command {} failed: {}", self.command, self.errmsg)
+    }
+}
+
+impl std::error::Error for ViciError {}
+
+impl From<ViciError> for io::Error {
+    fn from(value: ViciError) -> Self {
+        io::Error::other(value)
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct CountersMsg<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<&'a str>,
+}
+
+/// response of `get-counters`, counters are keyed by connection name, empty for global ones
+#[derive(Deserialize, Debug)]
+struct CountersResponse {
+    success: bool,
+    errmsg: Option<String>,
+    #[serde(default)]
+    counters: HashMap<String, HashMap<String, String>>,
+}
+
+impl CountersResponse {
+    /// counters of the connection, or the global ones
+    fn into_counters(mut self, name: Option<&str>) -> io::Result<HashMap<String, u64>> {
+        CommonResponse {
+            success: self.success,
+            errmsg: self.errmsg.take(),
         }
+        .ok_or("get-counters")?;
+
+        let counters = self.counters.remove(name.unwrap_or("")).unwrap_or_default();
+        counters
+            .into_iter()
+            .map(|(k, v)| match v.parse() {
+                Ok(v) => Ok((k, v)),
+                Err(e) => Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid value of counter {k}: {e}"),
+                )),
+            })
+            .collect()
     }
 }
 
@@ -115,21 +280,73 @@ struct Key<'a, 'b> {
     data: &'b str,
 }
 
+#[derive(Debug, Serialize)]
+struct Cert<'a, 'b> {
+    r#type: &'a str,
+    flag: &'a str,
+    data: &'b str,
+}
+
+/// message of `load-pool`, keyed by the name of pool
+#[derive(Debug, Serialize)]
+struct Pool<'a> {
+    addrs: &'a str,
+}
+
+impl<'a> Pool<'a> {
+    fn msg(name: &'a str, addrs: &'a str) -> HashMap<&'a str, Pool<'a>> {
+        HashMap::from([(name, Pool { addrs })])
+    }
+}
+
 #[derive(Debug, Serialize)]
 struct Authentication {
     auth: &'static str,
     pubkeys: Vec<String>,
     id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    revocation: Option<Revocation>,
 }
 
 #[derive(Debug, Serialize)]
 struct Child {
     local_ts: Vec<String>,
     remote_ts: Vec<String>,
-    mode: &'static str,
-    dpd_action: &'static str,
+    mode: ChildMode,
+    dpd_action: DpdAction,
     start_action: &'static str,
     close_action: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hw_offload: Option<HwOffload>,
+}
+
+/// traffic selectors covering everything
+const WILDCARD_TS: [&str; 2] = ["0.0.0.0/0", "::/0"];
+
+impl Child {
+    /// An on-demand CHILD_SA is trapped instead of being restarted, it is established by the
+    /// first packet matching the trap policy.
+    fn new(mode: ChildMode, dpd_action: DpdAction, on_demand: bool) -> Self {
+        // in transport mode, charon narrows `dynamic` to the addresses of the IKE_SA
+        let ts: Vec<String> = match mode {
+            ChildMode::Tunnel => WILDCARD_TS.iter().map(|ts| ts.to_string()).collect(),
+            ChildMode::Transport => vec!["dynamic".to_string()],
+        };
+
+        Child {
+            local_ts: ts.clone(),
+            remote_ts: ts,
+            mode,
+            dpd_action: if on_demand {
+                DpdAction::Trap
+            } else {
+                dpd_action
+            },
+            start_action: if on_demand { "trap" } else { "none" },
+            close_action: if on_demand { "trap" } else { "none" },
+            hw_offload: None,
+        }
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -141,16 +358,69 @@ struct Connection {
     remote_port: u16,
     encap: bool,
     mobike: bool,
+    make_before_break: bool,
     dpd_delay: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dpd_timeout: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reauth_time: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fragmentation: Option<Fragmentation>,
     keyingtries: u32,
     unique: &'static str,
-    if_id_in: &'static str,
-    if_id_out: &'static str,
+    if_id_in: String,
+    if_id_out: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pools: Vec<String>,
     local: Authentication,
     remote: Authentication,
     children: HashMap<&'static str, Child>,
 }
 
+impl Connection {
+    fn new(local: PeerConfig<'_>, remote: PeerConfig<'_>, options: &ConnectionOptions) -> Self {
+        let if_id = options
+            .if_id
+            .map(|id| id.to_string())
+            .unwrap_or("%unique".to_string());
+        let mut child = Child::new(options.child_mode, options.dpd_action, options.on_demand);
+        child.hw_offload = options.hw_offload;
+
+        Connection {
+            version: options.ike_version.unwrap_or(2),
+            local_addrs: local.addrs,
+            remote_addrs: remote.addrs,
+            local_port: options.local_port.unwrap_or(local.port),
+            remote_port: remote.port,
+            encap: options.encap.unwrap_or(true),
+            mobike: false,
+            make_before_break: options.make_before_break,
+            dpd_delay: 5,
+            dpd_timeout: options.dpd_timeout,
+            reauth_time: options.reauth_time,
+            fragmentation: options.fragmentation,
+            keyingtries: 0,
+            unique: "replace",
+            if_id_in: if_id.clone(),
+            if_id_out: if_id,
+            pools: options.pools.clone(),
+            local: Authentication {
+                auth: "pubkey",
+                pubkeys: vec![local.pubkey.to_string()],
+                id: local.id.to_string(),
+                revocation: None,
+            },
+            remote: Authentication {
+                auth: "pubkey",
+                pubkeys: vec![remote.pubkey.to_string()],
+                id: remote.id.to_string(),
+                revocation: options.revocation,
+            },
+            children: HashMap::from([("default", child)]),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct PeerConfig<'a> {
     pub id: &'a str,
@@ -159,26 +429,185 @@ pub struct PeerConfig<'a> {
     pub pubkey: &'a str,
 }
 
+/// Settings of a connection which aren't described by the peers.
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionOptions {
+    /// establish the new IKE_SA before deleting the old one on reauthentication
+    pub make_before_break: bool,
+    /// if_id for both directions, charon assigns a unique one if not set
+    pub if_id: Option<u32>,
+    pub dpd_action: DpdAction,
+    pub dpd_timeout: Option<u64>,
+    /// seconds between reauthentications of IKE_SA, only rekeyed if not set
+    pub reauth_time: Option<u64>,
+    pub child_mode: ChildMode,
+    pub hw_offload: Option<HwOffload>,
+    pub fragmentation: Option<Fragmentation>,
+    /// revocation checking of the certificate of the remote peer
+    pub revocation: Option<Revocation>,
+    /// source port of IKE packets instead of the port of the local endpoint
+    pub local_port: Option<u16>,
+    /// names of pools assigning virtual IPs to peers
+    pub pools: Vec<String>,
+    /// 1 for IKEv1, 2 for IKEv2 and 0 for any, IKEv2 if not set
+    pub ike_version: Option<u32>,
+    /// encapsulate ESP in UDP even if no NAT is detected, enabled if not set
+    pub encap: Option<bool>,
+    /// install trap policies instead of initiating the CHILD_SA
+    pub on_demand: bool,
+}
+
+/// Settings of an `initiate` request.
+#[derive(Debug, Clone, Copy)]
+pub struct InitiateOptions {
+    /// in milliseconds, negative value returns immediately without waiting for the result of
+    /// initiation
+    pub timeout: i32,
+    /// whether charon's limits of half-open IKE_SAs apply to this initiation
+    pub init_limits: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct InitiateMsg<'a> {
+    child: &'a str,
+    ike: &'a str,
+    timeout: i32,
+    init_limits: bool,
+}
+
+impl<'a> InitiateMsg<'a> {
+    fn new(name: &'a str, options: &InitiateOptions) -> Self {
+        InitiateMsg {
+            ike: name,
+            child: "default",
+            timeout: options.timeout,
+            init_limits: options.init_limits,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct TerminateMsg<'a> {
+    ike: &'a str,
+    timeout: i32,
+}
+
+/// Charon responds `CMD_UNKNOWN` to commands it doesn't implement, which rsvici converts to
+/// `Unsupported`. The command is named so that callers can tell it from a failure.
+fn classify_error(cmd: &str, e: io::Error) -> io::Error {
+    match e.kind() {
+        io::ErrorKind::Unsupported => io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("command {cmd} is not supported by charon"),
+        ),
+        _ => e,
+    }
+}
+
+/// Fails with `TimedOut` if `fut` isn't done in time, `what` is the subject of the error.
+async fn with_timeout<T>(
+    what: &str,
+    timeout: Option<Duration>,
+    fut: impl Future<Output = io::Result<T>>,
+) -> io::Result<T> {
+    let Some(timeout) = timeout else {
+        return fut.await;
+    };
+
+    tokio::time::timeout(timeout, fut)
+        .await
+        .unwrap_or_else(|_| {
+            Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("{what} timed out after {timeout:?}"),
+            ))
+        })
+}
+
 impl Client {
+    fn new(inner: rsvici::Client) -> Self {
+        Client {
+            inner,
+            request_timeout: None,
+        }
+    }
+
+    async fn call<Req: Serialize, Res: DeserializeOwned>(
+        &mut self,
+        cmd: &str,
+        req: Req,
+    ) -> io::Result<Res> {
+        let timeout = self.request_timeout;
+        self.call_with_timeout(cmd, req, timeout).await
+    }
+
+    async fn call_with_timeout<Req: Serialize, Res: DeserializeOwned>(
+        &mut self,
+        cmd: &str,
+        req: Req,
+        timeout: Option<Duration>,
+    ) -> io::Result<Res> {
+        let request = async {
+            self.request(cmd, req)
+                .await
+                .map_err(|e| classify_error(cmd, e.into()))
+        };
+
+        with_timeout(&format!("command {cmd}"), timeout, request).await
+    }
+
     pub async fn connect<P: AsRef<Path>>(path: P) -> io::Result<Client> {
         let c = rsvici::unix::connect(path).await?;
 
-        Ok(Client(c))
+        Ok(Client::new(c))
+    }
+
+    pub async fn connect_tcp<A: ToSocketAddrs>(addr: A) -> io::Result<Client> {
+        let stream = TcpStream::connect(addr).await?;
+
+        Ok(Client::new(rsvici::Client::new(stream)))
+    }
+
+    pub async fn connect_socket(socket: &ViciSocket, timeouts: ViciTimeouts) -> io::Result<Client> {
+        let connect = async {
+            match socket {
+                ViciSocket::Unix(path) => Client::connect(path).await,
+                ViciSocket::Tcp(addr) => Client::connect_tcp(addr.as_str()).await,
+            }
+        };
+        let mut client = with_timeout("connecting to vici", timeouts.connect, connect).await?;
+        client.request_timeout = timeouts.request;
+
+        Ok(client)
     }
 
     pub async fn version(&mut self) -> io::Result<Version> {
-        let v: Version = self.request("version", ()).await?;
+        let v: Version = self.call("version", ()).await?;
 
         Ok(v)
     }
 
+    pub async fn stats(&mut self) -> io::Result<Stats> {
+        let s: Stats = self.call("stats", ()).await?;
+
+        Ok(s)
+    }
+
+    /// IKE message counters of a connection, or global ones if `name` isn't set. Requires the
+    /// counters plugin of charon.
+    pub async fn get_counters(&mut self, name: Option<&str>) -> io::Result<HashMap<String, u64>> {
+        let r: CountersResponse = self.call("get-counters", CountersMsg { name }).await?;
+
+        r.into_counters(name)
+    }
+
     pub async fn get_conns(&mut self) -> io::Result<Vec<String>> {
         #[derive(Debug, Deserialize)]
         struct Conns {
             conns: Vec<String>,
         }
 
-        let r: Conns = self.request("get-conns", ()).await?;
+        let r: Conns = self.call("get-conns", ()).await?;
 
         Ok(r.conns)
     }
@@ -189,9 +618,29 @@ impl Client {
             data,
         };
 
-        let r: CommonResponse = self.request("load-key", key).await?;
+        let r: CommonResponse = self.call("load-key", key).await?;
 
-        r.ok_or()
+        r.ok_or("load-key")
+    }
+
+    /// `data` is a X.509 certificate in pem
+    pub async fn load_cert(&mut self, data: &str) -> io::Result<()> {
+        let cert = Cert {
+            r#type: "X509",
+            flag: "NONE",
+            data,
+        };
+
+        let r: CommonResponse = self.call("load-cert", cert).await?;
+
+        r.ok_or("load-cert")
+    }
+
+    /// `addrs` is either a subnet or a range of addresses in the form of `from-to`
+    pub async fn load_pool(&mut self, name: &str, addrs: &str) -> io::Result<()> {
+        let r: CommonResponse = self.call("load-pool", Pool::msg(name, addrs)).await?;
+
+        r.ok_or("load-pool")
     }
 
     pub async fn load_conn(
@@ -199,47 +648,13 @@ impl Client {
         name: &str,
         local: PeerConfig<'_>,
         remote: PeerConfig<'_>,
+        options: &ConnectionOptions,
     ) -> io::Result<()> {
-        let conn = Connection {
-            version: 2,
-            local_addrs: local.addrs,
-            remote_addrs: remote.addrs,
-            local_port: local.port,
-            remote_port: remote.port,
-            encap: true,
-            mobike: false,
-            dpd_delay: 5,
-            keyingtries: 0,
-            unique: "replace",
-            if_id_in: "%unique",
-            if_id_out: "%unique",
-            local: Authentication {
-                auth: "pubkey",
-                pubkeys: vec![local.pubkey.to_string()],
-                id: local.id.to_string(),
-            },
-            remote: Authentication {
-                auth: "pubkey",
-                pubkeys: vec![remote.pubkey.to_string()],
-                id: remote.id.to_string(),
-            },
-            children: HashMap::from([(
-                "default",
-                Child {
-                    local_ts: vec!["0.0.0.0/0".to_string(), "::/0".to_string()],
-                    remote_ts: vec!["0.0.0.0/0".to_string(), "::/0".to_string()],
-                    mode: "tunnel",
-                    dpd_action: "restart",
-                    start_action: "none",
-                    close_action: "none",
-                },
-            )]),
-        };
-
+        let conn = Connection::new(local, remote, options);
         let r: CommonResponse = self
-            .request("load-conn", HashMap::from([(name, conn)]))
+            .call("load-conn", HashMap::from([(name, conn)]))
             .await?;
-        r.ok_or()
+        r.ok_or("load-conn")
     }
 
     pub async fn unload_conn(&mut self, name: &str) -> io::Result<()> {
@@ -249,41 +664,898 @@ impl Client {
         }
 
         let msg = Msg { name };
-        let r: CommonResponse = self.request("unload-conn", msg).await?;
-        r.ok_or()
+        let r: CommonResponse = self.call("unload-conn", msg).await?;
+        r.ok_or("unload-conn")
     }
 
-    pub async fn initiate(&mut self, name: &str) -> io::Result<()> {
-        #[derive(Serialize)]
-        struct Msg<'a> {
-            child: &'a str,
-            ike: &'a str,
-            timeout: i32,
-            init_limits: bool,
+    pub async fn initiate(&mut self, name: &str, options: &InitiateOptions) -> io::Result<()> {
+        // charon responds once the initiation is done, which takes up to the timeout of it
+        let initiating = Duration::from_millis(options.timeout.max(0) as u64);
+        let timeout = self.request_timeout.map(|t| t + initiating);
+        let r: CommonResponse = self
+            .call_with_timeout("initiate", InitiateMsg::new(name, options), timeout)
+            .await?;
+        r.ok_or("initiate")
+    }
+
+    /// Terminate every IKE_SA of the connection, waiting up to `timeout` ms for the peer to
+    /// confirm.
+    pub async fn terminate(&mut self, name: &str, timeout: i32) -> io::Result<()> {
+        let terminating = Duration::from_millis(timeout.max(0) as u64);
+        let timeout_total = self.request_timeout.map(|t| t + terminating);
+        let r: CommonResponse = self
+            .call_with_timeout(
+                "terminate",
+                TerminateMsg { ike: name, timeout },
+                timeout_total,
+            )
+            .await?;
+        r.ok_or("terminate")
+    }
+
+    pub async fn list_sas(&mut self) -> Result<HashMap<String, IkeSa>, Box<dyn std::error::Error>> {
+        let timeout = self.request_timeout;
+        let list = async {
+            let sas = self.stream_request::<(), HashMap<String, IkeSa>>("list-sas", "list-sa", ());
+            pin_mut!(sas);
+
+            let mut s: HashMap<String, IkeSa> = HashMap::new();
+
+            while let Some(t) = sas.try_next().await.map_err(io::Error::from)? {
+                for (k, v) in t {
+                    s.insert(k, v);
+                }
+            }
+
+            Ok(s)
+        };
+
+        Ok(with_timeout("command list-sas", timeout, list).await?)
+    }
+}
+
+/// Vici requests used by the daemon, so that they can be served by something other than charon.
+pub trait ViciApi {
+    fn version(&mut self) -> impl Future<Output = io::Result<Version>>;
+
+    fn stats(&mut self) -> impl Future<Output = io::Result<Stats>>;
+
+    fn get_conns(&mut self) -> impl Future<Output = io::Result<Vec<String>>>;
+
+    fn get_counters(
+        &mut self,
+        name: Option<&str>,
+    ) -> impl Future<Output = io::Result<HashMap<String, u64>>>;
+
+    fn load_key(&mut self, data: &str) -> impl Future<Output = io::Result<()>>;
+
+    fn load_cert(&mut self, data: &str) -> impl Future<Output = io::Result<()>>;
+
+    fn load_pool(&mut self, name: &str, addrs: &str) -> impl Future<Output = io::Result<()>>;
+
+    fn load_conn(
+        &mut self,
+        name: &str,
+        local: PeerConfig<'_>,
+        remote: PeerConfig<'_>,
+        options: &ConnectionOptions,
+    ) -> impl Future<Output = io::Result<()>>;
+
+    fn unload_conn(&mut self, name: &str) -> impl Future<Output = io::Result<()>>;
+
+    fn initiate(
+        &mut self,
+        name: &str,
+        options: &InitiateOptions,
+    ) -> impl Future<Output = io::Result<()>>;
+
+    fn terminate(&mut self, name: &str, timeout: i32) -> impl Future<Output = io::Result<()>>;
+
+    fn list_sas(
+        &mut self,
+    ) -> impl Future<Output = Result<HashMap<String, IkeSa>, Box<dyn std::error::Error>>>;
+
+    fn subscribe<'a, T: DeserializeOwned + 'a>(
+        &'a mut self,
+        event: &'a str,
+    ) -> impl Stream<Item = io::Result<T>> + 'a;
+
+    /// Poll `version` until charon answers it. The socket accepts as soon as the vici plugin is
+    /// loaded, before other plugins are, so requests may fail right after connecting.
+    fn wait_ready(
+        &mut self,
+        interval: Duration,
+        timeout: Duration,
+    ) -> impl Future<Output = io::Result<Version>> {
+        async move {
+            let deadline = tokio::time::Instant::now() + timeout;
+            loop {
+                match self.version().await {
+                    Ok(version) => return Ok(version),
+                    Err(e) if tokio::time::Instant::now() >= deadline => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            format!("charon isn't ready after {timeout:?}: {e}"),
+                        ));
+                    }
+                    Err(e) => debug!("charon isn't ready yet: {e}"),
+                }
+                tokio::time::sleep(interval).await;
+            }
         }
+    }
+}
 
-        let msg = Msg {
-            ike: name,
-            child: "default",
-            timeout: -1,
-            init_limits: false,
+impl ViciApi for Client {
+    fn version(&mut self) -> impl Future<Output = io::Result<Version>> {
+        Client::version(self)
+    }
+
+    fn stats(&mut self) -> impl Future<Output = io::Result<Stats>> {
+        Client::stats(self)
+    }
+
+    fn get_conns(&mut self) -> impl Future<Output = io::Result<Vec<String>>> {
+        Client::get_conns(self)
+    }
+
+    fn get_counters(
+        &mut self,
+        name: Option<&str>,
+    ) -> impl Future<Output = io::Result<HashMap<String, u64>>> {
+        Client::get_counters(self, name)
+    }
+
+    fn load_key(&mut self, data: &str) -> impl Future<Output = io::Result<()>> {
+        Client::load_key(self, data)
+    }
+
+    fn load_cert(&mut self, data: &str) -> impl Future<Output = io::Result<()>> {
+        Client::load_cert(self, data)
+    }
+
+    fn load_pool(&mut self, name: &str, addrs: &str) -> impl Future<Output = io::Result<()>> {
+        Client::load_pool(self, name, addrs)
+    }
+
+    fn load_conn(
+        &mut self,
+        name: &str,
+        local: PeerConfig<'_>,
+        remote: PeerConfig<'_>,
+        options: &ConnectionOptions,
+    ) -> impl Future<Output = io::Result<()>> {
+        Client::load_conn(self, name, local, remote, options)
+    }
+
+    fn unload_conn(&mut self, name: &str) -> impl Future<Output = io::Result<()>> {
+        Client::unload_conn(self, name)
+    }
+
+    fn initiate(
+        &mut self,
+        name: &str,
+        options: &InitiateOptions,
+    ) -> impl Future<Output = io::Result<()>> {
+        Client::initiate(self, name, options)
+    }
+
+    fn terminate(&mut self, name: &str, timeout: i32) -> impl Future<Output = io::Result<()>> {
+        Client::terminate(self, name, timeout)
+    }
+
+    fn list_sas(
+        &mut self,
+    ) -> impl Future<Output = Result<HashMap<String, IkeSa>, Box<dyn std::error::Error>>> {
+        Client::list_sas(self)
+    }
+
+    fn subscribe<'a, T: DeserializeOwned + 'a>(
+        &'a mut self,
+        event: &'a str,
+    ) -> impl Stream<Item = io::Result<T>> + 'a {
+        self.inner.subscribe::<T>(event).map_err(io::Error::from)
+    }
+}
+
+/// Opens connections to vici, every long-running task of the daemon holds its own one.
+pub trait ViciConnector {
+    type Client: ViciApi;
+
+    fn connect(&self) -> impl Future<Output = io::Result<Self::Client>>;
+}
+
+/// vici socket which charon listens on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ViciSocket {
+    Unix(PathBuf),
+    /// address and port, e.g. `127.0.0.1:4502`
+    Tcp(String),
+}
+
+impl ViciSocket {
+    /// uri of the socket in strongswan.conf
+    pub fn uri(&self) -> String {
+        match self {
+            ViciSocket::Unix(path) => format!("unix://{}", path.display()),
+            ViciSocket::Tcp(addr) => format!("tcp://{addr}"),
+        }
+    }
+}
+
+/// connects to vici socket of charon
+pub struct SocketConnector(pub ViciSocket, pub ViciTimeouts);
+
+impl ViciConnector for SocketConnector {
+    type Client = Client;
+
+    async fn connect(&self) -> io::Result<Client> {
+        Client::connect_socket(&self.0, self.1).await
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod mock {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+
+    /// state of the fake charon shared by all connections of a MockConnector
+    #[derive(Default)]
+    pub struct MockState {
+        pub keys: Vec<String>,
+        pub certs: Vec<String>,
+        /// name and addrs of loaded pools
+        pub pools: Vec<(String, String)>,
+        pub conns: Vec<String>,
+        /// connections which have an established IKE_SA
+        pub sas: Vec<String>,
+        pub initiated: Vec<String>,
+        pub terminated: Vec<String>,
+        /// errmsg returned for every initiation, SAs are established immediately if not set
+        pub initiate_error: Option<String>,
+        /// connecting fails as if charon isn't running
+        pub unreachable: bool,
+        /// number of `load-key` requests failing before one succeeds, as if charon isn't ready
+        pub load_key_failures: usize,
+        /// number of `version` requests failing before one succeeds
+        pub version_failures: usize,
+    }
+
+    #[derive(Clone, Default)]
+    pub struct MockConnector(pub Rc<RefCell<MockState>>);
+
+    pub struct MockVici(Rc<RefCell<MockState>>);
+
+    impl ViciConnector for MockConnector {
+        type Client = MockVici;
+
+        async fn connect(&self) -> io::Result<MockVici> {
+            if self.0.borrow().unreachable {
+                return Err(io::Error::from(io::ErrorKind::ConnectionRefused));
+            }
+            Ok(MockVici(Rc::clone(&self.0)))
+        }
+    }
+
+    impl ViciApi for MockVici {
+        async fn version(&mut self) -> io::Result<Version> {
+            let mut state = self.0.borrow_mut();
+            if state.version_failures > 0 {
+                state.version_failures -= 1;
+                return Err(io::Error::other(
+                    "command version failed: plugins not loaded",
+                ));
+            }
+            Ok(Version {
+                daemon: "charon".to_string(),
+                version: "6.0.1".to_string(),
+                sysname: "Linux".to_string(),
+                release: "6.1.0".to_string(),
+                machine: "x86_64".to_string(),
+            })
+        }
+
+        async fn stats(&mut self) -> io::Result<Stats> {
+            let sas = self.0.borrow().sas.len();
+            Ok(Stats {
+                ikesas: IkeSaStats {
+                    total: sas.to_string(),
+                    half_open: "0".to_string(),
+                },
+                ..Default::default()
+            })
+        }
+
+        async fn get_conns(&mut self) -> io::Result<Vec<String>> {
+            Ok(self.0.borrow().conns.clone())
+        }
+
+        async fn get_counters(&mut self, _name: Option<&str>) -> io::Result<HashMap<String, u64>> {
+            let initiated = self.0.borrow().initiated.len() as u64;
+            Ok(HashMap::from([("ike-init-out-req".to_string(), initiated)]))
+        }
+
+        async fn load_key(&mut self, data: &str) -> io::Result<()> {
+            let mut state = self.0.borrow_mut();
+            if state.load_key_failures > 0 {
+                state.load_key_failures -= 1;
+                return Err(io::Error::from(io::ErrorKind::ConnectionReset));
+            }
+            state.keys.push(data.to_string());
+            Ok(())
+        }
+
+        async fn load_cert(&mut self, data: &str) -> io::Result<()> {
+            self.0.borrow_mut().certs.push(data.to_string());
+            Ok(())
+        }
+
+        async fn load_pool(&mut self, name: &str, addrs: &str) -> io::Result<()> {
+            self.0
+                .borrow_mut()
+                .pools
+                .push((name.to_string(), addrs.to_string()));
+            Ok(())
+        }
+
+        async fn load_conn(
+            &mut self,
+            name: &str,
+            _local: PeerConfig<'_>,
+            _remote: PeerConfig<'_>,
+            _options: &ConnectionOptions,
+        ) -> io::Result<()> {
+            let mut state = self.0.borrow_mut();
+            if !state.conns.iter().any(|c| c == name) {
+                state.conns.push(name.to_string());
+            }
+            Ok(())
+        }
+
+        async fn unload_conn(&mut self, name: &str) -> io::Result<()> {
+            let mut state = self.0.borrow_mut();
+            state.conns.retain(|c| c != name);
+            state.sas.retain(|c| c != name);
+            Ok(())
+        }
+
+        async fn initiate(&mut self, name: &str, _options: &InitiateOptions) -> io::Result<()> {
+            let mut state = self.0.borrow_mut();
+            state.initiated.push(name.to_string());
+            if let Some(e) = state.initiate_error.clone() {
+                return Err(io::Error::other(e));
+            }
+
+            state.sas.push(name.to_string());
+            Ok(())
+        }
+
+        async fn terminate(&mut self, name: &str, _timeout: i32) -> io::Result<()> {
+            let mut state = self.0.borrow_mut();
+            state.terminated.push(name.to_string());
+            state.sas.retain(|c| c != name);
+            Ok(())
+        }
+
+        async fn list_sas(&mut self) -> Result<HashMap<String, IkeSa>, Box<dyn std::error::Error>> {
+            let sas = self.0.borrow().sas.clone();
+            Ok(sas
+                .into_iter()
+                .map(|name| {
+                    let sa = IkeSa {
+                        if_id_in: "00000001".to_string(),
+                        if_id_out: "00000001".to_string(),
+                        local_id: String::new(),
+                        remote_id: String::new(),
+                    };
+                    (name, sa)
+                })
+                .collect())
+        }
+
+        fn subscribe<'a, T: DeserializeOwned + 'a>(
+            &'a mut self,
+            _event: &'a str,
+        ) -> impl Stream<Item = io::Result<T>> + 'a {
+            futures::stream::pending()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::mock::{MockConnector, MockState};
+    use super::*;
+
+    fn peers() -> (PeerConfig<'static>, PeerConfig<'static>) {
+        (
+            PeerConfig {
+                id: "O=example,CN=local,serialNumber=0",
+                addrs: vec!["1.1.1.1".to_string()],
+                port: 12025,
+                pubkey: "local-pubkey",
+            },
+            PeerConfig {
+                id: "O=example,CN=remote,serialNumber=0",
+                addrs: vec!["2.2.2.2".to_string()],
+                port: 12345,
+                pubkey: "remote-pubkey",
+            },
+        )
+    }
+
+    #[test]
+    fn test_vici_error() {
+        let r = CommonResponse {
+            success: false,
+            errmsg: Some("duplicate connection".to_string()),
+        };
+        let e = r.ok_or("load-conn").unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "command load-conn failed: duplicate connection"
+        );
+        let vici = ViciError::from_io(&e).unwrap();
+        assert_eq!(vici.command, "load-conn");
+        assert_eq!(vici.errmsg, "duplicate connection");
+        assert!(!vici.success);
+
+        let r = CommonResponse {
+            success: true,
+            errmsg: None,
         };
-        let r: CommonResponse = self.request("initiate", msg).await?;
-        r.ok_or()
+        assert!(r.ok_or("load-conn").is_ok());
+        assert!(ViciError::from_io(&io::Error::other("other")).is_none());
     }
 
-    pub async fn list_sas(&mut self) -> Result<HashMap<String, IkeSa>, Box<dyn std::error::Error>> {
-        let sas = self.stream_request::<(), HashMap<String, IkeSa>>("list-sas", "list-sa", ());
-        pin_mut!(sas);
+    #[test]
+    fn test_connection_make_before_break() {
+        let (local, remote) = peers();
+        let conn = serde_json::to_value(Connection::new(
+            local,
+            remote,
+            &ConnectionOptions::default(),
+        ))
+        .unwrap();
+        assert_eq!(conn["make_before_break"], false);
+
+        let (local, remote) = peers();
+        let options = ConnectionOptions {
+            make_before_break: true,
+            ..Default::default()
+        };
+        let conn = serde_json::to_value(Connection::new(local, remote, &options)).unwrap();
+        assert_eq!(conn["make_before_break"], true);
+    }
+
+    #[test]
+    fn test_connection_reauth_time() {
+        let (local, remote) = peers();
+        let conn = serde_json::to_value(Connection::new(
+            local,
+            remote,
+            &ConnectionOptions::default(),
+        ))
+        .unwrap();
+        assert!(conn.get("reauth_time").is_none());
+
+        let (local, remote) = peers();
+        let options = ConnectionOptions {
+            reauth_time: Some(86400),
+            make_before_break: true,
+            ..Default::default()
+        };
+        let conn = serde_json::to_value(Connection::new(local, remote, &options)).unwrap();
+        assert_eq!(conn["reauth_time"], 86400);
+        assert_eq!(conn["make_before_break"], true);
+    }
 
-        let mut s: HashMap<String, IkeSa> = HashMap::new();
+    #[test]
+    fn test_updown_unknown_keys() {
+        let message = serde_vici::to_vec(&serde_json::json!({
+            "up": true,
+            "new-top-level": "1",
+            "nested": { "foo": "bar" },
+            "conn-a": {
+                "if-id-in": "00000001",
+                "if-id-out": "00000001",
+                "local-id": "O=example,CN=local,serialNumber=0",
+                "remote-id": "O=example,CN=remote,serialNumber=0",
+                "state": "ESTABLISHED"
+            }
+        }))
+        .unwrap();
+        let event: Updown = serde_vici::from_slice(&message).unwrap();
 
-        while let Some(t) = sas.try_next().await? {
-            for (k, v) in t {
-                s.insert(k, v);
+        assert_eq!(event.up, Some(true));
+        assert_eq!(event.ike_sas.len(), 1);
+        assert_eq!(event.ike_sas["conn-a"].if_id_in, "00000001");
+    }
+
+    #[test]
+    fn test_get_counters() {
+        let response = r#"{
+            "success": true,
+            "counters": {
+                "conn-a": {
+                    "ike-rekey-init": "1",
+                    "ike-init-out-req": "12",
+                    "ike-init-out-res": "0"
+                }
+            }
+        }"#;
+
+        let r: CountersResponse = serde_json::from_str(response).unwrap();
+        let counters = r.into_counters(Some("conn-a")).unwrap();
+        assert_eq!(counters.len(), 3);
+        assert_eq!(counters["ike-init-out-req"], 12);
+
+        // global counters weren't requested
+        let r: CountersResponse = serde_json::from_str(response).unwrap();
+        assert!(r.into_counters(None).unwrap().is_empty());
+
+        let r: CountersResponse =
+            serde_json::from_str(r#"{ "success": false, "errmsg": "no counters available" }"#)
+                .unwrap();
+        assert_eq!(
+            r.into_counters(None).unwrap_err().to_string(),
+            "command get-counters failed: no counters available"
+        );
+    }
+
+    #[test]
+    fn test_rekey_events() {
+        let event: IkeRekey = serde_json::from_str(
+            r#"{
+                "conn-a": {
+                    "old": {
+                        "uniqueid": "1",
+                        "state": "ESTABLISHED",
+                        "local-id": "O=example,CN=local,serialNumber=0",
+                        "remote-id": "O=example,CN=remote,serialNumber=0"
+                    },
+                    "new": {
+                        "uniqueid": "2",
+                        "state": "ESTABLISHED",
+                        "local-id": "O=example,CN=local,serialNumber=0",
+                        "remote-id": "O=example,CN=remote,serialNumber=0"
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(event["conn-a"].old.uniqueid, "1");
+        assert_eq!(event["conn-a"].new.uniqueid, "2");
+
+        let event: ChildRekey = serde_json::from_str(
+            r#"{
+                "conn-a": {
+                    "uniqueid": "2",
+                    "local-id": "O=example,CN=local,serialNumber=0",
+                    "remote-id": "O=example,CN=remote,serialNumber=0",
+                    "child-sas": {
+                        "default-3": {
+                            "old": { "uniqueid": "3", "spi-in": "c1a3e7b2", "spi-out": "c8e2d114" },
+                            "new": { "uniqueid": "4", "spi-in": "cb0f4e21", "spi-out": "c2d7a9f0" }
+                        }
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+        let child = &event["conn-a"].child_sas["default-3"];
+        assert_eq!(child.old.spi_in, "c1a3e7b2");
+        assert_eq!(child.new.uniqueid, "4");
+    }
+
+    #[test]
+    fn test_connection_dpd() {
+        let (local, remote) = peers();
+        let conn = serde_json::to_value(Connection::new(
+            local,
+            remote,
+            &ConnectionOptions::default(),
+        ))
+        .unwrap();
+        assert_eq!(conn["children"]["default"]["dpd_action"], "restart");
+        assert!(conn.get("dpd_timeout").is_none());
+
+        let (local, remote) = peers();
+        let options = ConnectionOptions {
+            dpd_action: DpdAction::Clear,
+            dpd_timeout: Some(30),
+            ..Default::default()
+        };
+        let conn = serde_json::to_value(Connection::new(local, remote, &options)).unwrap();
+        assert_eq!(conn["children"]["default"]["dpd_action"], "clear");
+        assert_eq!(conn["dpd_timeout"], 30);
+    }
+
+    #[test]
+    fn test_connection_if_id() {
+        let (local, remote) = peers();
+        let conn = serde_json::to_value(Connection::new(
+            local,
+            remote,
+            &ConnectionOptions::default(),
+        ))
+        .unwrap();
+        assert_eq!(conn["if_id_in"], "%unique");
+        assert_eq!(conn["if_id_out"], "%unique");
+
+        let (local, remote) = peers();
+        let options = ConnectionOptions {
+            if_id: Some(42),
+            ..Default::default()
+        };
+        let conn = serde_json::to_value(Connection::new(local, remote, &options)).unwrap();
+        assert_eq!(conn["if_id_in"], "42");
+        assert_eq!(conn["if_id_out"], "42");
+    }
+
+    #[test]
+    fn test_connection_ports() {
+        let (local, remote) = peers();
+        let conn = serde_json::to_value(Connection::new(
+            local,
+            remote,
+            &ConnectionOptions::default(),
+        ))
+        .unwrap();
+        assert_eq!(conn["local_port"], 12025);
+        assert_eq!(conn["remote_port"], 12345);
+
+        let (local, remote) = peers();
+        let options = ConnectionOptions {
+            local_port: Some(4500),
+            ..Default::default()
+        };
+        let conn = serde_json::to_value(Connection::new(local, remote, &options)).unwrap();
+        assert_eq!(conn["local_port"], 4500);
+        assert_eq!(conn["remote_port"], 12345);
+    }
+
+    #[test]
+    fn test_pools() {
+        let msg = serde_json::to_value(Pool::msg("aronet", "10.0.0.0/24")).unwrap();
+        assert_eq!(
+            msg,
+            serde_json::json!({ "aronet": { "addrs": "10.0.0.0/24" } })
+        );
+
+        let (local, remote) = peers();
+        let conn = serde_json::to_value(Connection::new(
+            local,
+            remote,
+            &ConnectionOptions::default(),
+        ))
+        .unwrap();
+        assert!(conn.get("pools").is_none());
+
+        let (local, remote) = peers();
+        let options = ConnectionOptions {
+            pools: vec!["aronet".to_string()],
+            ..Default::default()
+        };
+        let conn = serde_json::to_value(Connection::new(local, remote, &options)).unwrap();
+        assert_eq!(conn["pools"], serde_json::json!(["aronet"]));
+    }
+
+    #[test]
+    fn test_connection_version() {
+        let (local, remote) = peers();
+        let conn = serde_json::to_value(Connection::new(
+            local,
+            remote,
+            &ConnectionOptions::default(),
+        ))
+        .unwrap();
+        assert_eq!(conn["version"], 2);
+
+        let (local, remote) = peers();
+        let options = ConnectionOptions {
+            ike_version: Some(1),
+            ..Default::default()
+        };
+        let conn = serde_json::to_value(Connection::new(local, remote, &options)).unwrap();
+        assert_eq!(conn["version"], 1);
+    }
+
+    #[test]
+    fn test_unknown_command() {
+        let e = classify_error(
+            "get-pools",
+            io::Error::new(io::ErrorKind::Unsupported, "unknown command"),
+        );
+        assert_eq!(e.kind(), io::ErrorKind::Unsupported);
+        assert_eq!(
+            e.to_string(),
+            "command get-pools is not supported by charon"
+        );
+
+        // only the kind counts, not the message
+        let e = classify_error("get-pools", io::Error::other("unknown command"));
+        assert_eq!(e.kind(), io::ErrorKind::Other);
+        assert_eq!(e.to_string(), "unknown command");
+    }
+
+    #[test]
+    fn test_child_mode() {
+        let (local, remote) = peers();
+        let conn = serde_json::to_value(Connection::new(
+            local,
+            remote,
+            &ConnectionOptions::default(),
+        ))
+        .unwrap();
+        assert_eq!(conn["children"]["default"]["mode"], "tunnel");
+        assert_eq!(
+            conn["children"]["default"]["local_ts"],
+            serde_json::json!(["0.0.0.0/0", "::/0"])
+        );
+
+        let (local, remote) = peers();
+        let options = ConnectionOptions {
+            child_mode: ChildMode::Transport,
+            ..Default::default()
+        };
+        let conn = serde_json::to_value(Connection::new(local, remote, &options)).unwrap();
+        assert_eq!(conn["children"]["default"]["mode"], "transport");
+        assert_eq!(
+            conn["children"]["default"]["remote_ts"],
+            serde_json::json!(["dynamic"])
+        );
+    }
+
+    #[test]
+    fn test_connection_options() {
+        use serde_json::{Value, json};
+
+        // JSON pointers into the connection and their values, `None` if the key is left out
+        let cases: Vec<(ConnectionOptions, Vec<(&str, Option<Value>)>)> = vec![
+            (
+                ConnectionOptions::default(),
+                vec![
+                    ("/fragmentation", None),
+                    ("/remote/revocation", None),
+                    ("/children/default/hw_offload", None),
+                    ("/children/default/start_action", Some(json!("none"))),
+                    ("/children/default/close_action", Some(json!("none"))),
+                    ("/children/default/dpd_action", Some(json!("restart"))),
+                ],
+            ),
+            (
+                ConnectionOptions {
+                    fragmentation: Some(Fragmentation::Force),
+                    ..Default::default()
+                },
+                vec![("/fragmentation", Some(json!("force")))],
+            ),
+            (
+                ConnectionOptions {
+                    revocation: Some(Revocation::Ifuri),
+                    ..Default::default()
+                },
+                // only certificates of peers are checked
+                vec![
+                    ("/remote/revocation", Some(json!("ifuri"))),
+                    ("/local/revocation", None),
+                ],
+            ),
+            (
+                ConnectionOptions {
+                    hw_offload: Some(HwOffload::Auto),
+                    ..Default::default()
+                },
+                vec![("/children/default/hw_offload", Some(json!("auto")))],
+            ),
+            (
+                ConnectionOptions {
+                    on_demand: true,
+                    ..Default::default()
+                },
+                vec![
+                    ("/children/default/start_action", Some(json!("trap"))),
+                    ("/children/default/close_action", Some(json!("trap"))),
+                    ("/children/default/dpd_action", Some(json!("trap"))),
+                ],
+            ),
+        ];
+
+        for (options, expected) in cases {
+            let (local, remote) = peers();
+            let conn = serde_json::to_value(Connection::new(local, remote, &options)).unwrap();
+            for (path, value) in expected {
+                assert_eq!(conn.pointer(path), value.as_ref(), "{path} of {options:?}");
             }
         }
+    }
 
-        Ok(s)
+    #[tokio::test]
+    async fn test_connect_tcp() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        let socket = ViciSocket::Tcp(addr.clone());
+        assert_eq!(socket.uri(), format!("tcp://{addr}"));
+        let (client, accepted) = tokio::join!(
+            Client::connect_socket(&socket, ViciTimeouts::default()),
+            listener.accept()
+        );
+        assert!(client.is_ok());
+        assert!(accepted.is_ok());
+
+        drop(listener);
+        assert!(Client::connect_tcp(addr.as_str()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_wait_ready() {
+        let connector = MockConnector(Rc::new(RefCell::new(MockState {
+            version_failures: 3,
+            ..Default::default()
+        })));
+        let mut vici = connector.connect().await.unwrap();
+        let version = vici
+            .wait_ready(Duration::from_millis(5), Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(version.daemon, "charon");
+        // returned only after the failing requests
+        assert_eq!(connector.0.borrow().version_failures, 0);
+
+        connector.0.borrow_mut().version_failures = usize::MAX;
+        let e = vici
+            .wait_ready(Duration::from_millis(5), Duration::from_millis(20))
+            .await
+            .unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[tokio::test]
+    async fn test_request_timeout() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let socket = ViciSocket::Tcp(listener.local_addr().unwrap().to_string());
+        let timeouts = ViciTimeouts {
+            connect: Some(Duration::from_secs(1)),
+            request: Some(Duration::from_millis(50)),
+        };
+
+        // the other end accepts but never responds
+        let (client, accepted) =
+            tokio::join!(Client::connect_socket(&socket, timeouts), listener.accept());
+        let mut client = client.unwrap();
+        let _accepted = accepted.unwrap();
+
+        let e = client.version().await.unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+        assert!(client.list_sas().await.is_err());
+
+        // an idle subscription outlives the request timeout
+        let events = ViciApi::subscribe::<Updown>(&mut client, "ike-updown");
+        pin_mut!(events);
+        let idle = tokio::time::timeout(Duration::from_millis(200), events.try_next()).await;
+        assert!(idle.is_err());
+    }
+
+    #[test]
+    fn test_initiate_msg() {
+        let options = InitiateOptions {
+            timeout: 5000,
+            init_limits: true,
+        };
+        let msg = serde_json::to_value(InitiateMsg::new("conn-a", &options)).unwrap();
+        assert_eq!(
+            msg,
+            serde_json::json!({
+                "child": "default",
+                "ike": "conn-a",
+                "timeout": 5000,
+                "init_limits": true
+            })
+        );
     }
 }